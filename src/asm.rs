@@ -0,0 +1,552 @@
+use crate::error::AsmError;
+use crate::instruction::Instruction;
+use crate::PROGRAM_START;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+const MNEMONICS: [&str; 32] = [
+    "CLS", "RET", "SCD", "SCR", "SCL", "EXIT", "LOW", "HIGH", "JP", "CALL", "SE", "SNE", "SAVE",
+    "LOAD", "LD", "ADD", "OR", "AND", "XOR", "SUB", "SUBN", "SHR", "SHL", "RND", "DRW", "SKP",
+    "SKNP", "PLANE", "AUDIO", "PITCH", "DB", "DW",
+];
+
+/// Names that are operands and so can't be used for labels or constants.
+const RESERVED: [&str; 10] = ["I", "DT", "ST", "K", "F", "HF", "B", "R", "LONG", "INCLUDE"];
+
+/// Assembles `source` into a ROM that is loaded at `PROGRAM_START`.
+///
+/// The syntax is the one `disasm` prints, one statement per line:
+///
+/// - `; comment` anywhere on a line
+/// - `name:` defines a label, optionally followed by a statement
+/// - `name = value` defines a constant, values used before must be defined
+/// - `CLS`, `LD VA, 0x02`, `JP loop`, ... are instructions, see `Instruction`
+/// - `DB 1, 2, 3` and `DW 0x1234` emit bytes and big endian words
+/// - `INCLUDE "file.asm"` assembles another file in place, relative to the
+///   including one
+///
+/// Values are decimal, `0x` hex or `0b` binary numbers, labels or constants,
+/// combined with `+` and `-`. Mnemonics and registers ignore case.
+pub fn assemble(source: &str) -> Result<Vec<u8>, AsmError> {
+    let mut lines = Vec::new();
+    read_lines(source, None, 0, &mut lines)?;
+    Assembler::new().run(&lines)
+}
+
+/// Like `assemble`, reading the source from `path`.
+pub fn assemble_file(path: impl AsRef<Path>) -> Result<Vec<u8>, AsmError> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path).map_err(|err| AsmError {
+        file: Some(path.display().to_string()),
+        line: 0,
+        column: 0,
+        message: err.to_string(),
+    })?;
+    let mut lines = Vec::new();
+    read_lines(&source, Some(path), 0, &mut lines)?;
+    Assembler::new().run(&lines)
+}
+
+struct SourceLine {
+    file: Option<String>,
+    line: usize,
+    text: String,
+}
+
+impl SourceLine {
+    fn error(&self, column: usize, message: impl Into<String>) -> AsmError {
+        AsmError {
+            file: self.file.clone(),
+            line: self.line,
+            column: column + 1,
+            message: message.into(),
+        }
+    }
+}
+
+/// Collects the lines of `source` with comments removed and includes
+/// replaced by the lines of the included file.
+fn read_lines(
+    source: &str,
+    file: Option<&Path>,
+    depth: usize,
+    lines: &mut Vec<SourceLine>,
+) -> Result<(), AsmError> {
+    for (index, text) in source.lines().enumerate() {
+        let line = SourceLine {
+            file: file.map(|file| file.display().to_string()),
+            line: index + 1,
+            text: text.split(';').next().unwrap_or("").to_string(),
+        };
+        let start = line.text.len() - line.text.trim_start().len();
+        let mut words = line.text[start..].splitn(2, char::is_whitespace);
+        if !words
+            .next()
+            .is_some_and(|word| word.eq_ignore_ascii_case("include"))
+        {
+            lines.push(line);
+            continue;
+        }
+        let argument = words.next().unwrap_or("").trim();
+        let column = line.text.find(argument).unwrap_or(start);
+        let name = argument
+            .strip_prefix('"')
+            .and_then(|name| name.strip_suffix('"'))
+            .ok_or_else(|| line.error(column, "Expected a quoted file name"))?;
+        if depth >= MAX_INCLUDE_DEPTH {
+            return Err(line.error(column, "Includes are nested too deeply"));
+        }
+        let path = match file.and_then(Path::parent) {
+            Some(directory) => directory.join(name),
+            None => PathBuf::from(name),
+        };
+        let included = fs::read_to_string(&path)
+            .map_err(|err| line.error(column, format!("Could not include {}: {}", name, err)))?;
+        read_lines(&included, Some(&path), depth + 1, lines)?;
+    }
+    Ok(())
+}
+
+/// The instruction or data of a line, `column` is where its mnemonic starts.
+struct Statement<'a> {
+    source: &'a SourceLine,
+    mnemonic: String,
+    column: usize,
+    operands: Vec<(&'a str, usize)>,
+}
+
+enum Operand {
+    Register(u8),
+    Range(u8, u8),
+    I,
+    IndirectI,
+    Delay,
+    Sound,
+    Key,
+    Font,
+    BigFont,
+    Bcd,
+    Flags,
+    Long(i64, usize),
+    Value(i64, usize),
+}
+
+struct Assembler {
+    symbols: HashMap<String, i64>,
+}
+
+impl Assembler {
+    fn new() -> Assembler {
+        Assembler {
+            symbols: HashMap::new(),
+        }
+    }
+
+    fn run(mut self, lines: &[SourceLine]) -> Result<Vec<u8>, AsmError> {
+        // The first pass finds where every statement goes so labels can be
+        // used before they are defined.
+        let mut statements = Vec::new();
+        let mut address = PROGRAM_START as usize;
+        for line in lines {
+            let Some(statement) = self.parse_line(line, address as u16)? else {
+                continue;
+            };
+            address += statement_size(&statement);
+            if address > 0x10000 {
+                return Err(line.error(statement.column, "Program does not fit into memory"));
+            }
+            statements.push(statement);
+        }
+
+        let mut rom = Vec::new();
+        for statement in &statements {
+            rom.extend(self.encode(statement)?);
+        }
+        Ok(rom)
+    }
+
+    /// Defines the label and constant of a line and returns its statement.
+    fn parse_line<'a>(
+        &mut self,
+        line: &'a SourceLine,
+        address: u16,
+    ) -> Result<Option<Statement<'a>>, AsmError> {
+        let text = line.text.as_str();
+        let mut position = skip_whitespace(text, 0);
+        let word_end = |start: usize| {
+            text[start..]
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .map_or(text.len(), |len| start + len)
+        };
+
+        let end = word_end(position);
+        let after = skip_whitespace(text, end);
+        if end > position && text[after..].starts_with(':') {
+            self.define(line, &text[position..end], position, address as i64)?;
+            position = skip_whitespace(text, after + 1);
+        }
+        if position == text.len() {
+            return Ok(None);
+        }
+
+        let end = word_end(position);
+        let after = skip_whitespace(text, end);
+        if end > position && text[after..].starts_with('=') {
+            let value_start = skip_whitespace(text, after + 1);
+            let value = self.value(line, text[value_start..].trim_end(), value_start)?;
+            self.define(line, &text[position..end], position, value)?;
+            return Ok(None);
+        }
+        if end == position {
+            return Err(line.error(position, "Expected an instruction"));
+        }
+        if end < text.len() && after == end {
+            let unexpected = text[end..].chars().next().unwrap_or_default();
+            return Err(line.error(end, format!("Unexpected {}", unexpected)));
+        }
+
+        let mut operands = Vec::new();
+        if after < text.len() {
+            let mut start = after;
+            for operand in text[after..].split(',') {
+                let trimmed = operand.trim();
+                let column = start + operand.len() - operand.trim_start().len();
+                if trimmed.is_empty() {
+                    return Err(line.error(column, "Expected an operand"));
+                }
+                operands.push((trimmed, column));
+                start += operand.len() + 1;
+            }
+        }
+        Ok(Some(Statement {
+            source: line,
+            mnemonic: text[position..end].to_ascii_uppercase(),
+            column: position,
+            operands,
+        }))
+    }
+
+    fn define(
+        &mut self,
+        line: &SourceLine,
+        name: &str,
+        column: usize,
+        value: i64,
+    ) -> Result<(), AsmError> {
+        let upper = name.to_ascii_uppercase();
+        if name.starts_with(|c: char| c.is_ascii_digit())
+            || RESERVED.contains(&upper.as_str())
+            || parse_register(&upper).is_some()
+        {
+            return Err(line.error(column, format!("{} can't be used as a name", name)));
+        }
+        if self.symbols.insert(name.to_string(), value).is_some() {
+            return Err(line.error(column, format!("{} is defined twice", name)));
+        }
+        Ok(())
+    }
+
+    /// Evaluates a sum of numbers and symbols like `sprites + 5`.
+    fn value(&self, line: &SourceLine, text: &str, column: usize) -> Result<i64, AsmError> {
+        let mut total = 0;
+        let mut sign = 1;
+        let mut expect_term = true;
+        let mut position = 0;
+        while position < text.len() {
+            let c = text.as_bytes()[position];
+            if c.is_ascii_whitespace() {
+                position += 1;
+                continue;
+            }
+            if c == b'+' || c == b'-' {
+                if c == b'-' {
+                    sign = -sign;
+                }
+                expect_term = true;
+                position += 1;
+                continue;
+            }
+            if !expect_term {
+                return Err(line.error(column + position, "Expected + or -"));
+            }
+            let end = text[position..]
+                .find(|c: char| c.is_whitespace() || c == '+' || c == '-')
+                .map_or(text.len(), |len| position + len);
+            let term = &text[position..end];
+            let value = match parse_number(term) {
+                Some(value) => value,
+                None => *self.symbols.get(term).ok_or_else(|| {
+                    line.error(column + position, format!("Unknown value {}", term))
+                })?,
+            };
+            total = value
+                .checked_mul(sign)
+                .and_then(|value| value.checked_add(total))
+                .ok_or_else(|| line.error(column + position, "Value out of range"))?;
+            sign = 1;
+            expect_term = false;
+            position = end;
+        }
+        if expect_term {
+            return Err(line.error(column + text.len(), "Expected a value"));
+        }
+        Ok(total)
+    }
+
+    fn operand(&self, line: &SourceLine, text: &str, column: usize) -> Result<Operand, AsmError> {
+        let upper = text.to_ascii_uppercase();
+        let operand = match upper.as_str() {
+            "I" => Operand::I,
+            "[I]" => Operand::IndirectI,
+            "DT" => Operand::Delay,
+            "ST" => Operand::Sound,
+            "K" => Operand::Key,
+            "F" => Operand::Font,
+            "HF" => Operand::BigFont,
+            "B" => Operand::Bcd,
+            "R" => Operand::Flags,
+            _ => {
+                if let Some(x) = parse_register(&upper) {
+                    return Ok(Operand::Register(x));
+                }
+                if let Some((x, y)) = upper.split_once('-') {
+                    if let (Some(x), Some(y)) = (parse_register(x.trim()), parse_register(y.trim()))
+                    {
+                        return Ok(Operand::Range(x, y));
+                    }
+                }
+                if let Some(rest) = upper.strip_prefix("LONG") {
+                    if rest.starts_with(char::is_whitespace) {
+                        let offset = text.len() - rest.trim_start().len();
+                        let value = self.value(line, &text[offset..], column + offset)?;
+                        return Ok(Operand::Long(value, column + offset));
+                    }
+                }
+                Operand::Value(self.value(line, text, column)?, column)
+            }
+        };
+        Ok(operand)
+    }
+
+    fn encode(&self, statement: &Statement) -> Result<Vec<u8>, AsmError> {
+        let line = statement.source;
+        if !MNEMONICS.contains(&statement.mnemonic.as_str()) {
+            return Err(line.error(
+                statement.column,
+                format!("Unknown instruction {}", statement.mnemonic),
+            ));
+        }
+        let operands = statement
+            .operands
+            .iter()
+            .map(|&(text, column)| self.operand(line, text, column))
+            .collect::<Result<Vec<Operand>, AsmError>>()?;
+        let ranged = |value: i64, column: usize, min: i64, max: i64| {
+            if value < min || value > max {
+                Err(line.error(
+                    column,
+                    format!("{} is out of range {}..={}", value, min, max),
+                ))
+            } else {
+                Ok(value)
+            }
+        };
+        let address = |value, column| ranged(value, column, 0, 0xFFF).map(|value| value as u16);
+        let byte = |value, column| ranged(value, column, -0x80, 0xFF).map(|value| value as u8);
+        let nibble = |value, column| ranged(value, column, 0, 0xF).map(|value| value as u8);
+
+        match statement.mnemonic.as_str() {
+            "DB" | "DW" if operands.is_empty() => {
+                return Err(line.error(statement.column, "Expected data"))
+            }
+            "DB" => {
+                return operands
+                    .iter()
+                    .map(|operand| match *operand {
+                        Operand::Value(value, column) => byte(value, column),
+                        _ => Err(line.error(statement.column, "DB takes numbers")),
+                    })
+                    .collect()
+            }
+            "DW" => {
+                let mut bytes = Vec::new();
+                for operand in &operands {
+                    let Operand::Value(value, column) = *operand else {
+                        return Err(line.error(statement.column, "DW takes numbers"));
+                    };
+                    let word = ranged(value, column, -0x8000, 0xFFFF)? as u16;
+                    bytes.extend(word.to_be_bytes());
+                }
+                return Ok(bytes);
+            }
+            _ => (),
+        }
+
+        use Operand::*;
+        let instruction = match (statement.mnemonic.as_str(), operands.as_slice()) {
+            ("CLS", []) => Instruction::ClearScreen,
+            ("RET", []) => Instruction::Return,
+            ("SCD", &[Value(n, column)]) => Instruction::ScrollDown {
+                n: nibble(n, column)?,
+            },
+            ("SCR", []) => Instruction::ScrollRight,
+            ("SCL", []) => Instruction::ScrollLeft,
+            ("EXIT", []) => Instruction::Exit,
+            ("LOW", []) => Instruction::LowRes,
+            ("HIGH", []) => Instruction::HighRes,
+            ("JP", &[Value(target, column)]) => Instruction::Jump {
+                address: address(target, column)?,
+            },
+            ("JP", &[Register(0), Value(target, column)]) => {
+                let target = address(target, column)?;
+                Instruction::JumpOffset {
+                    x: (target >> 8) as u8,
+                    address: target,
+                }
+            }
+            ("CALL", &[Value(target, column)]) => Instruction::Call {
+                address: address(target, column)?,
+            },
+            ("SE", &[Register(x), Value(value, column)]) => Instruction::SkipEqualImmediate {
+                x,
+                value: byte(value, column)?,
+            },
+            ("SE", &[Register(x), Register(y)]) => Instruction::SkipEqual { x, y },
+            ("SNE", &[Register(x), Value(value, column)]) => Instruction::SkipNotEqualImmediate {
+                x,
+                value: byte(value, column)?,
+            },
+            ("SNE", &[Register(x), Register(y)]) => Instruction::SkipNotEqual { x, y },
+            ("SAVE", &[Range(x, y)]) => Instruction::SaveRange { x, y },
+            ("LOAD", &[Range(x, y)]) => Instruction::LoadRange { x, y },
+            ("LD", &[Register(x), Value(value, column)]) => Instruction::LoadImmediate {
+                x,
+                value: byte(value, column)?,
+            },
+            ("LD", &[Register(x), Register(y)]) => Instruction::Move { x, y },
+            ("LD", &[I, Value(target, column)]) => Instruction::LoadIndex {
+                address: address(target, column)?,
+            },
+            ("LD", &[I, Long(target, column)]) => Instruction::LoadLongIndex {
+                address: ranged(target, column, 0, 0xFFFF)? as u16,
+            },
+            ("LD", &[Register(x), Delay]) => Instruction::GetDelay { x },
+            ("LD", &[Register(x), Key]) => Instruction::WaitKey { x },
+            ("LD", &[Delay, Register(x)]) => Instruction::SetDelay { x },
+            ("LD", &[Sound, Register(x)]) => Instruction::SetSound { x },
+            ("LD", &[Font, Register(x)]) => Instruction::Font { x },
+            ("LD", &[BigFont, Register(x)]) => Instruction::BigFont { x },
+            ("LD", &[Bcd, Register(x)]) => Instruction::Bcd { x },
+            ("LD", &[IndirectI, Register(x)]) => Instruction::Store { x },
+            ("LD", &[Register(x), IndirectI]) => Instruction::Load { x },
+            ("LD", &[Flags, Register(x)]) => Instruction::SaveFlags { x },
+            ("LD", &[Register(x), Flags]) => Instruction::LoadFlags { x },
+            ("ADD", &[Register(x), Value(value, column)]) => Instruction::AddImmediate {
+                x,
+                value: byte(value, column)?,
+            },
+            ("ADD", &[Register(x), Register(y)]) => Instruction::AddReg { x, y },
+            ("ADD", &[I, Register(x)]) => Instruction::AddIndex { x },
+            ("OR", &[Register(x), Register(y)]) => Instruction::Or { x, y },
+            ("AND", &[Register(x), Register(y)]) => Instruction::And { x, y },
+            ("XOR", &[Register(x), Register(y)]) => Instruction::Xor { x, y },
+            ("SUB", &[Register(x), Register(y)]) => Instruction::Sub { x, y },
+            ("SUBN", &[Register(x), Register(y)]) => Instruction::SubReverse { x, y },
+            ("SHR", &[Register(x)]) => Instruction::ShiftRight { x, y: x },
+            ("SHR", &[Register(x), Register(y)]) => Instruction::ShiftRight { x, y },
+            ("SHL", &[Register(x)]) => Instruction::ShiftLeft { x, y: x },
+            ("SHL", &[Register(x), Register(y)]) => Instruction::ShiftLeft { x, y },
+            ("RND", &[Register(x), Value(mask, column)]) => Instruction::Random {
+                x,
+                mask: byte(mask, column)?,
+            },
+            ("DRW", &[Register(x), Register(y), Value(n, column)]) => Instruction::Draw {
+                x,
+                y,
+                n: nibble(n, column)?,
+            },
+            ("SKP", &[Register(x)]) => Instruction::SkipKeyPressed { x },
+            ("SKNP", &[Register(x)]) => Instruction::SkipKeyNotPressed { x },
+            ("PLANE", &[Value(planes, column)]) => Instruction::SelectPlanes {
+                planes: nibble(planes, column)?,
+            },
+            ("AUDIO", []) => Instruction::LoadAudioPattern,
+            ("PITCH", &[Register(x)]) => Instruction::Pitch { x },
+            _ => {
+                return Err(line.error(
+                    statement.column,
+                    format!("Invalid operands for {}", statement.mnemonic),
+                ))
+            }
+        };
+        Ok(instruction.encode())
+    }
+}
+
+/// Bytes a statement takes, known before any operand is evaluated.
+fn statement_size(statement: &Statement) -> usize {
+    match statement.mnemonic.as_str() {
+        "DB" => statement.operands.len(),
+        "DW" => statement.operands.len() * 2,
+        "LD" if statement.operands.get(1).is_some_and(|(operand, _)| {
+            operand
+                .get(..5)
+                .is_some_and(|prefix| prefix.eq_ignore_ascii_case("LONG "))
+        }) =>
+        {
+            4
+        }
+        _ => 2,
+    }
+}
+
+fn skip_whitespace(text: &str, start: usize) -> usize {
+    text[start..]
+        .find(|c: char| !c.is_whitespace())
+        .map_or(text.len(), |offset| start + offset)
+}
+
+/// `V0` to `VF`, with `name` already in upper case.
+fn parse_register(name: &str) -> Option<u8> {
+    let index = name.strip_prefix('V')?;
+    if index.len() != 1 {
+        return None;
+    }
+    u8::from_str_radix(index, 16).ok()
+}
+
+fn parse_number(text: &str) -> Option<i64> {
+    let lower = text.to_ascii_lowercase();
+    if let Some(hex) = lower.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = lower.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2).ok()
+    } else if lower.starts_with(|c: char| c.is_ascii_digit()) {
+        lower.parse().ok()
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn non_ascii_source_is_an_error() {
+        for source in ["LD V0, abcdé", "CLSé", "é", "LD V0, é + 1", "DB ÄÖÜ"] {
+            assert!(assemble(source).is_err(), "{}", source);
+        }
+        let error = assemble("CLSé").unwrap_err();
+        assert_eq!((error.column, error.message.as_str()), (4, "Unexpected é"));
+        assert_eq!(assemble("; é\nCLS ; ü").unwrap(), [0x00, 0xE0]);
+    }
+
+    #[test]
+    fn huge_values_are_an_error() {
+        assert!(assemble("DW 0x7FFFFFFFFFFFFFFF + 1").is_err());
+    }
+}
@@ -0,0 +1,179 @@
+use std::f32::consts::TAU;
+use std::fmt;
+use std::str::FromStr;
+
+/// How long starting and stopping the buzzer fades in and out. Cutting a wave
+/// off mid-period is audible as a click.
+const FADE_SECONDS: f32 = 0.005;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Waveform {
+    #[default]
+    Square,
+    Triangle,
+    Sawtooth,
+    Sine,
+}
+
+impl Waveform {
+    /// The wave at `phase` in `0.0..1.0`, between -1 and 1.
+    fn sample(self, phase: f32) -> f32 {
+        match self {
+            Waveform::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+            Waveform::Sawtooth => 2.0 * phase - 1.0,
+            Waveform::Sine => (phase * TAU).sin(),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Waveform::Square => "square",
+            Waveform::Triangle => "triangle",
+            Waveform::Sawtooth => "sawtooth",
+            Waveform::Sine => "sine",
+        }
+    }
+}
+
+impl fmt::Display for Waveform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Waveform {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Waveform, String> {
+        [
+            Waveform::Square,
+            Waveform::Triangle,
+            Waveform::Sawtooth,
+            Waveform::Sine,
+        ]
+        .into_iter()
+        .find(|waveform| waveform.name().eq_ignore_ascii_case(name))
+        .ok_or_else(|| format!("Unknown waveform {}", name))
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AudioSettings {
+    /// Pitch of the buzzer in Hz.
+    pub frequency: f32,
+    /// Volume between 0 and 1.
+    pub volume: f32,
+    pub waveform: Waveform,
+}
+
+impl Default for AudioSettings {
+    fn default() -> AudioSettings {
+        AudioSettings {
+            frequency: 440.0,
+            volume: 0.25,
+            waveform: Waveform::Square,
+        }
+    }
+}
+
+/// An XO-CHIP audio pattern: 128 one bit samples, played at a rate in Hz.
+pub type AudioPattern = ([u8; 16], f32);
+
+/// Produces the samples of the buzzer.
+#[derive(Clone, Debug)]
+pub struct ToneGenerator {
+    settings: AudioSettings,
+    phase: f32,
+    level: f32,
+    beeping: bool,
+    /// Played instead of the tone of `settings` if set.
+    pattern: Option<AudioPattern>,
+    /// Position in the pattern, in bits.
+    bit: f32,
+}
+
+impl ToneGenerator {
+    pub fn new(settings: AudioSettings) -> ToneGenerator {
+        ToneGenerator {
+            settings,
+            phase: 0.0,
+            level: 0.0,
+            beeping: false,
+            pattern: None,
+            bit: 0.0,
+        }
+    }
+
+    pub fn set_beeping(&mut self, beeping: bool) {
+        self.beeping = beeping;
+    }
+
+    pub fn set_pattern(&mut self, pattern: Option<AudioPattern>) {
+        self.pattern = pattern;
+    }
+
+    /// Fills `samples` with mono audio at `sample_rate`.
+    pub fn fill(&mut self, samples: &mut [f32], sample_rate: u32) {
+        let sample_rate = sample_rate as f32;
+        let fade_step = 1.0 / (FADE_SECONDS * sample_rate);
+        let target = if self.beeping { 1.0 } else { 0.0 };
+        for sample in samples.iter_mut() {
+            if self.level < target {
+                self.level = (self.level + fade_step).min(target);
+            } else if self.level > target {
+                self.level = (self.level - fade_step).max(target);
+            }
+            if self.level == 0.0 {
+                // Restart silent waves at the beginning of a period.
+                self.phase = 0.0;
+                self.bit = 0.0;
+                *sample = 0.0;
+                continue;
+            }
+            let wave = match self.pattern {
+                Some((pattern, rate)) => {
+                    let bit = self.bit as usize;
+                    let set = pattern[bit / 8] & (0x80 >> (bit % 8)) != 0;
+                    self.bit = (self.bit + rate / sample_rate) % 128.0;
+                    if set {
+                        1.0
+                    } else {
+                        -1.0
+                    }
+                }
+                None => {
+                    let wave = self.settings.waveform.sample(self.phase);
+                    self.phase = (self.phase + self.settings.frequency / sample_rate).fract();
+                    wave
+                }
+            };
+            *sample = wave * self.level * self.settings.volume;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patterns_play_their_bits() {
+        let mut generator = ToneGenerator::new(AudioSettings::default());
+        let mut pattern = [0; 16];
+        pattern[0] = 0xF0;
+        // One bit per sample.
+        generator.set_pattern(Some((pattern, 8000.0)));
+        generator.set_beeping(true);
+        let mut samples = [0.0; 16];
+        generator.fill(&mut samples, 8000);
+        assert!(samples[..4].iter().all(|&sample| sample > 0.0));
+        assert!(samples[4..].iter().all(|&sample| sample < 0.0));
+    }
+}
@@ -0,0 +1,255 @@
+use crate::audio::AudioSettings;
+use crate::frontend::{AudioRecorder, Palette};
+use crate::screen::{even_scale, LORES_HEIGHT, LORES_WIDTH};
+use crate::{Screen, FRAME_RATE};
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// An image of the screen with one palette index, the planes lit, per pixel.
+pub(crate) struct Frame {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+}
+
+impl Frame {
+    /// With `scale` the image is as big as the window at that scale, 64x32
+    /// times `even_scale(scale)` in both resolutions. `None` gives one image
+    /// pixel per CHIP-8 pixel.
+    pub fn new(screen: &Screen, scale: Option<usize>) -> Frame {
+        let pixel_size = match scale {
+            Some(scale) => LORES_WIDTH * even_scale(scale) / screen.width(),
+            None => 1,
+        };
+        let width = screen.width() * pixel_size;
+        let height = screen.height() * pixel_size;
+        let mut pixels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                pixels.push(screen.get(x / pixel_size, y / pixel_size));
+            }
+        }
+        Frame {
+            width,
+            height,
+            pixels,
+        }
+    }
+}
+
+/// The four colors of `palette` as RGB triples, background first.
+pub(crate) fn palette_rgb(palette: &Palette) -> Vec<u8> {
+    (0..4).flat_map(|planes| palette.color(planes)).collect()
+}
+
+/// Writes the screen as a palette PNG in the colors of `palette`, see
+/// `Frame::new` for `scale`.
+pub fn write_png(
+    screen: &Screen,
+    palette: &Palette,
+    scale: Option<usize>,
+    out: impl Write,
+) -> io::Result<()> {
+    let frame = Frame::new(screen, scale);
+    let mut encoder = png::Encoder::new(out, frame.width as u32, frame.height as u32);
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_palette(palette_rgb(palette));
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&frame.pixels)?;
+    writer.finish()?;
+    Ok(())
+}
+
+pub fn save_png(
+    screen: &Screen,
+    palette: &Palette,
+    scale: Option<usize>,
+    path: impl AsRef<Path>,
+) -> io::Result<()> {
+    let file = BufWriter::new(File::create(path)?);
+    write_png(screen, palette, scale, file)
+}
+
+/// Records the screen of every frame as a looping GIF. Frames that look
+/// like the one before only keep it on screen longer.
+pub struct GifRecorder<W: Write> {
+    encoder: gif::Encoder<W>,
+    scale: usize,
+    /// The last image, written once it is known how long it stays.
+    pending: Option<Frame>,
+    /// The frame the pending image first showed on.
+    shown_at: u64,
+    frames: u64,
+}
+
+impl<W: Write> GifRecorder<W> {
+    /// `scale` works like the window's. A GIF can't change its size when the
+    /// program switches resolution, so there is no native size.
+    pub fn new(out: W, palette: &Palette, scale: usize) -> io::Result<GifRecorder<W>> {
+        let scale = even_scale(scale);
+        let mut encoder = gif::Encoder::new(
+            out,
+            (LORES_WIDTH * scale) as u16,
+            (LORES_HEIGHT * scale) as u16,
+            &palette_rgb(palette),
+        )
+        .map_err(io::Error::other)?;
+        encoder
+            .set_repeat(gif::Repeat::Infinite)
+            .map_err(io::Error::other)?;
+        Ok(GifRecorder {
+            encoder,
+            scale,
+            pending: None,
+            shown_at: 0,
+            frames: 0,
+        })
+    }
+
+    /// Adds the screen as it shows for the next 1/60 s.
+    pub fn add_frame(&mut self, screen: &Screen) -> io::Result<()> {
+        let frame = Frame::new(screen, Some(self.scale));
+        let same = self
+            .pending
+            .as_ref()
+            .is_some_and(|pending| pending.pixels == frame.pixels);
+        // GIF delays are in 1/100 s and browsers slow down frames shorter
+        // than 2/100 s, those are left out while the timing stays right.
+        let too_short = centiseconds(self.frames) - centiseconds(self.shown_at) < 2;
+        if !same {
+            if self.pending.is_none() || !too_short {
+                self.write_pending()?;
+                self.shown_at = self.frames;
+            }
+            self.pending = Some(frame);
+        }
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// Writes the last frame and the end of the GIF.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write_pending()?;
+        self.encoder.into_inner().map_err(io::Error::other)
+    }
+
+    fn write_pending(&mut self) -> io::Result<()> {
+        let Some(frame) = self.pending.take() else {
+            return Ok(());
+        };
+        let delay = centiseconds(self.frames) - centiseconds(self.shown_at);
+        let gif_frame = gif::Frame {
+            width: frame.width as u16,
+            height: frame.height as u16,
+            delay: delay.min(u16::MAX as u64) as u16,
+            buffer: Cow::Owned(frame.pixels),
+            ..gif::Frame::default()
+        };
+        self.encoder
+            .write_frame(&gif_frame)
+            .map_err(io::Error::other)
+    }
+}
+
+/// When `frame` starts, in 1/100 s. Rounding the start times keeps delays
+/// of 2, 2 and 1 adding up to 60 frames per second.
+fn centiseconds(frame: u64) -> u64 {
+    (frame * 100 + FRAME_RATE as u64 / 2) / FRAME_RATE as u64
+}
+
+/// Writes the buzzer `recorder` heard as 16 bit mono WAV.
+pub fn save_wav(
+    recorder: &AudioRecorder,
+    settings: AudioSettings,
+    path: impl AsRef<Path>,
+) -> io::Result<()> {
+    const SAMPLE_RATE: u32 = 44_100;
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate: SAMPLE_RATE,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::create(path, spec).map_err(io::Error::other)?;
+    for sample in recorder.samples(settings, SAMPLE_RATE) {
+        let sample = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+        writer.write_sample(sample).map_err(io::Error::other)?;
+    }
+    writer.finalize().map_err(io::Error::other)
+}
+
+/// A file name like `pong-20261018-142305-120.png`, the current UTC time
+/// down to the millisecond after `prefix`.
+pub fn timestamped_name(prefix: &str, extension: &str) -> String {
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let seconds = since_epoch.as_secs();
+    let (year, month, day) = civil_date(seconds / 86_400);
+    let time = seconds % 86_400;
+    format!(
+        "{}-{:04}{:02}{:02}-{:02}{:02}{:02}-{:03}.{}",
+        prefix,
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60,
+        since_epoch.subsec_millis(),
+        extension
+    )
+}
+
+/// The Gregorian date `days` days after 1970-01-01, after Howard Hinnant's
+/// `civil_from_days`.
+fn civil_date(days: u64) -> (u64, u64, u64) {
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + (month <= 2) as u64;
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gif_frames_fill_the_canvas() {
+        let mut hires = Screen::new();
+        hires.set_hires(true);
+        for scale in 1..=5 {
+            let canvas = (
+                LORES_WIDTH * even_scale(scale),
+                LORES_HEIGHT * even_scale(scale),
+            );
+            for screen in [Screen::new(), hires.clone()] {
+                let frame = Frame::new(&screen, Some(scale));
+                assert_eq!((frame.width, frame.height), canvas);
+            }
+        }
+        let mut recorder = GifRecorder::new(Vec::new(), &Palette::default(), 1).unwrap();
+        recorder.add_frame(&Screen::new()).unwrap();
+        recorder.add_frame(&hires).unwrap();
+        let gif = recorder.finish().unwrap();
+        let mut decoder = gif::DecodeOptions::new().read_info(gif.as_slice()).unwrap();
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            assert_eq!((frame.width, frame.height), (128, 64));
+        }
+    }
+}
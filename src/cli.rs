@@ -0,0 +1,205 @@
+use crate::config::{Screenshots, Settings};
+use chip_8_emulator::frontend::{Keymap, Palette};
+use chip_8_emulator::{ErrorPolicy, Quirks, RngMode, Variant};
+use clap::{Args, CommandFactory, FromArgMatches, Parser, Subcommand};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// A CHIP-8, SUPER-CHIP and XO-CHIP emulator.
+///
+/// Without a subcommand the ROM is run, `chip_8_emulator game.ch8` is the
+/// same as `chip_8_emulator run game.ch8`.
+///
+/// The top level arguments of `run` are added in `parse`, clap can't flatten
+/// them into an `Option` here as they are nested.
+#[derive(Parser, Debug)]
+#[command(
+    version,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+/// Parses the command line, exiting with a message if it is invalid.
+pub fn parse() -> Command {
+    let command = RunArgs::augment_args(Cli::command());
+    let matches = command.clone().get_matches();
+    let parsed = Cli::from_arg_matches(&matches)
+        .and_then(|cli| match cli.command {
+            Some(subcommand) => Ok(subcommand),
+            None => RunArgs::from_arg_matches(&matches).map(Command::Run),
+        })
+        .map_err(|err| err.format(&mut command.clone()));
+    parsed.unwrap_or_else(|err| err.exit())
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Run a ROM or an Octo source file
+    Run(RunArgs),
+    /// Print a ROM as assembler source
+    Disasm { rom: PathBuf },
+    /// Assemble a source file into a ROM
+    Asm {
+        source: PathBuf,
+        /// Where to write the ROM, by default next to the source with a .ch8 extension
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Run a ROM without a window and print the screen it ends with
+    Test(TestArgs),
+    /// Show what is known about a ROM
+    Info {
+        rom: PathBuf,
+        /// Config file next to whose programs.json the ROM is also looked up
+        #[arg(long)]
+        config: Option<PathBuf>,
+    },
+}
+
+/// Options shared by everything that runs a ROM.
+#[derive(Args, Debug, Clone)]
+pub struct MachineArgs {
+    /// A ROM, or Octo source ending in .8o
+    pub rom: PathBuf,
+    /// Instructions per second, rounded to whole instructions per frame
+    #[arg(long)]
+    pub ips: Option<u32>,
+    /// Instruction set: chip-8, schip or xo-chip
+    #[arg(long, value_parser = Variant::from_str)]
+    pub variant: Option<Variant>,
+    /// A quirks preset (vip, chip-48, schip, xo-chip) and/or quirks to turn
+    /// on or off, e.g. `vip,shift,no-clipping`
+    #[arg(long, value_parser = Quirks::parse)]
+    pub quirks: Option<Quirks>,
+    /// Seed for the random number generator, makes runs reproducible
+    #[arg(long)]
+    pub seed: Option<u64>,
+    /// Random number generator for CXNN: xorshift or table, which adds up
+    /// bytes of a fixed table
+    #[arg(long)]
+    pub rng: Option<RngMode>,
+    /// What to do when an instruction faults: halt, skip it and report the
+    /// fault, or treat it as a silent no-op
+    #[arg(long)]
+    pub on_error: Option<ErrorPolicy>,
+    /// Config file to use instead of the one in the user's config directory
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct RunArgs {
+    #[command(flatten)]
+    pub machine: MachineArgs,
+    /// Window size as a multiple of 64x32, odd scales are rounded up
+    #[arg(long, value_parser = parse_scale)]
+    pub scale: Option<usize>,
+    /// Background, plane 1, plane 2 and plane 1+2 colors, e.g. `000000,ffffff`
+    #[arg(long, value_parser = Palette::parse)]
+    pub palette: Option<Palette>,
+    /// A keymap preset (qwerty, vip, azerty, numpad, hex-labels) or the keys for CHIP-8
+    /// keys 0 to F, e.g. `x123qweasdzc4rfv`
+    #[arg(long, value_parser = parse_keymap)]
+    pub keymap: Option<String>,
+    /// Start with the buzzer muted
+    #[arg(long)]
+    pub mute: bool,
+    /// Run without a window, as fast as possible
+    #[arg(long)]
+    pub headless: bool,
+    /// Stop after this many frames
+    #[arg(long)]
+    pub frames: Option<u64>,
+    /// Start from a save state
+    #[arg(long)]
+    pub load_state: Option<PathBuf>,
+    /// Record the keys pressed into a movie file, to replay the run exactly
+    #[arg(long, conflicts_with_all = ["play", "load_state"])]
+    pub record: Option<PathBuf>,
+    /// Replay a movie file made with --record
+    #[arg(long, conflicts_with = "load_state")]
+    pub play: Option<PathBuf>,
+    /// Let a key press take over from the movie and record from there on,
+    /// into the movie file
+    #[arg(long, requires = "play")]
+    pub read_write: bool,
+    /// Save a screenshot after this frame, can be given several times
+    #[arg(long, value_name = "FRAME")]
+    pub screenshot_at: Vec<u64>,
+    /// Directory for screenshots, next to the ROM by default
+    #[arg(long, value_name = "DIR")]
+    pub screenshot_dir: Option<PathBuf>,
+    /// Record the run as an animated GIF
+    #[arg(long, value_name = "FILE")]
+    pub gif: Option<PathBuf>,
+    /// Write every frame as a PNG and the buzzer as audio.wav into a
+    /// directory, e.g. to make a video of the run
+    #[arg(long, value_name = "DIR")]
+    pub raw_frames: Option<PathBuf>,
+    /// Print the configuration the ROM would run with and exit
+    #[arg(long)]
+    pub print_config: bool,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct TestArgs {
+    #[command(flatten)]
+    pub machine: MachineArgs,
+    /// How many frames to run
+    #[arg(long, default_value_t = 600)]
+    pub frames: u64,
+    /// Fail unless the final screen matches this file, as printed by `test`
+    #[arg(long)]
+    pub expect: Option<PathBuf>,
+    /// Press the keys of a movie file made with `run --record`
+    #[arg(long)]
+    pub play: Option<PathBuf>,
+}
+
+impl MachineArgs {
+    /// The options given on the command line, which override the config file.
+    pub fn settings(&self) -> Settings {
+        Settings {
+            ips: self.ips,
+            variant: self.variant,
+            quirks: self.quirks,
+            rng: self.rng,
+            seed: self.seed,
+            on_error: self.on_error,
+            ..Settings::default()
+        }
+    }
+}
+
+impl RunArgs {
+    pub fn settings(&self) -> Settings {
+        Settings {
+            scale: self.scale,
+            palette: self.palette,
+            keymap: self.keymap.clone(),
+            mute: self.mute.then_some(true),
+            screenshots: Screenshots {
+                directory: self.screenshot_dir.clone(),
+                native: None,
+            },
+            ..self.machine.settings()
+        }
+    }
+}
+
+/// Checks the keymap but keeps it as text, the way the config file has it.
+fn parse_keymap(spec: &str) -> Result<String, String> {
+    Keymap::parse(spec).map(|_| spec.to_string())
+}
+
+fn parse_scale(text: &str) -> Result<usize, String> {
+    match text.parse() {
+        Ok(0) => Err("The scale has to be at least 1".to_string()),
+        Ok(scale) => Ok(scale),
+        Err(_) => Err(format!("{} is not a number", text)),
+    }
+}
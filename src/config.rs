@@ -0,0 +1,326 @@
+use chip_8_emulator::audio::{AudioSettings, Waveform};
+use chip_8_emulator::frontend::{Keymap, Palette};
+use chip_8_emulator::rewind::RewindBuffer;
+use chip_8_emulator::romdb::Entry;
+use chip_8_emulator::{ErrorPolicy, Quirks, RngMode, Variant};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// The options that can be set in the config file. Unset ones fall back to
+/// the layer below: built in defaults, then `[default]`, then the ROM
+/// database, then the section of the ROM, then the command line.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    /// Only to tell ROM sections apart, it is not used otherwise.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ips: Option<u32>,
+    #[serde(with = "as_text", skip_serializing_if = "Option::is_none")]
+    pub variant: Option<Variant>,
+    #[serde(with = "as_text", skip_serializing_if = "Option::is_none")]
+    pub quirks: Option<Quirks>,
+    /// How `CXNN` makes random numbers.
+    #[serde(with = "as_text", skip_serializing_if = "Option::is_none")]
+    pub rng: Option<RngMode>,
+    /// Makes the random numbers the same on every run, differ every run if unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    /// What happens when an instruction faults: halt, skip or nop.
+    #[serde(with = "as_text", skip_serializing_if = "Option::is_none")]
+    pub on_error: Option<ErrorPolicy>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scale: Option<usize>,
+    #[serde(with = "as_text", skip_serializing_if = "Option::is_none")]
+    pub palette: Option<Palette>,
+    /// A keymap preset or 16 keys, see `Keymap::parse`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keymap: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mute: Option<bool>,
+    /// More host keys for CHIP-8 keys on top of the keymap, e.g.
+    /// `5 = ["Up", "Pad Up"]`. See `sdl::resolve_keymap` for the names.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub keys: BTreeMap<String, Vec<String>>,
+    pub audio: Audio,
+    pub rewind: Rewind,
+    pub screenshots: Screenshots,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Audio {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub volume: Option<f32>,
+    #[serde(with = "as_text", skip_serializing_if = "Option::is_none")]
+    pub waveform: Option<Waveform>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Rewind {
+    /// How far back the rewind hotkey goes, 0 turns rewinding off.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seconds: Option<u32>,
+    /// The most memory the rewind buffer may take, in MiB.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory: Option<u32>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Screenshots {
+    /// Where screenshots go, next to the ROM if unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub directory: Option<PathBuf>,
+    /// One image pixel per CHIP-8 pixel instead of the window's scale.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub native: Option<bool>,
+}
+
+impl Settings {
+    /// What is used when neither the config file nor the command line say otherwise.
+    pub fn builtin() -> Settings {
+        let audio = AudioSettings::default();
+        Settings {
+            name: None,
+            ips: Some(
+                chip_8_emulator::DEFAULT_INSTRUCTIONS_PER_FRAME * chip_8_emulator::FRAME_RATE,
+            ),
+            variant: Some(Variant::SuperChip),
+            quirks: Some(Quirks::default()),
+            rng: Some(RngMode::default()),
+            seed: None,
+            on_error: Some(ErrorPolicy::default()),
+            #[cfg(feature = "sdl")]
+            scale: Some(chip_8_emulator::sdl::DEFAULT_SCALE),
+            #[cfg(not(feature = "sdl"))]
+            scale: None,
+            palette: Some(Palette::default()),
+            keymap: Some(Keymap::PRESETS[0].to_string()),
+            mute: Some(false),
+            keys: BTreeMap::new(),
+            audio: Audio {
+                frequency: Some(audio.frequency),
+                volume: Some(audio.volume),
+                waveform: Some(audio.waveform),
+            },
+            rewind: Rewind {
+                seconds: Some(30),
+                memory: Some(64),
+            },
+            screenshots: Screenshots {
+                directory: None,
+                native: Some(false),
+            },
+        }
+    }
+
+    /// `self` with the options set in `over` replaced. Keys bound in both
+    /// get the host keys of `over` only.
+    pub fn merge(self, over: Settings) -> Settings {
+        let mut keys = self.keys;
+        keys.extend(over.keys);
+        Settings {
+            name: over.name.or(self.name),
+            ips: over.ips.or(self.ips),
+            variant: over.variant.or(self.variant),
+            quirks: over.quirks.or(self.quirks),
+            rng: over.rng.or(self.rng),
+            seed: over.seed.or(self.seed),
+            on_error: over.on_error.or(self.on_error),
+            scale: over.scale.or(self.scale),
+            palette: over.palette.or(self.palette),
+            keymap: over.keymap.or(self.keymap),
+            mute: over.mute.or(self.mute),
+            keys,
+            audio: Audio {
+                frequency: over.audio.frequency.or(self.audio.frequency),
+                volume: over.audio.volume.or(self.audio.volume),
+                waveform: over.audio.waveform.or(self.audio.waveform),
+            },
+            rewind: Rewind {
+                seconds: over.rewind.seconds.or(self.rewind.seconds),
+                memory: over.rewind.memory.or(self.rewind.memory),
+            },
+            screenshots: Screenshots {
+                directory: over.screenshots.directory.or(self.screenshots.directory),
+                native: over.screenshots.native.or(self.screenshots.native),
+            },
+        }
+    }
+
+    /// The settings the ROM database has for a ROM. The controls it knows
+    /// go on the arrow keys, space and return as well as game controllers.
+    pub fn from_entry(entry: &Entry) -> Settings {
+        let mut keys: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for (action, &key) in &entry.keys {
+            if let Some(&(_, hosts)) = ACTION_KEYS.iter().find(|(name, _)| name == action) {
+                keys.entry(format!("{:X}", key))
+                    .or_default()
+                    .extend(hosts.map(str::to_string));
+            }
+        }
+        Settings {
+            name: Some(entry.title.clone()),
+            ips: Some(entry.tickrate * chip_8_emulator::FRAME_RATE),
+            variant: Some(entry.variant),
+            quirks: Some(entry.quirks),
+            palette: entry.palette,
+            keys,
+            ..Settings::default()
+        }
+    }
+
+    #[cfg(feature = "sdl")]
+    pub fn keymap(&self) -> Result<Keymap, String> {
+        let mut keymap = Keymap::parse(self.keymap.as_deref().unwrap_or(Keymap::PRESETS[0]))?;
+        for (key, hosts) in &self.keys {
+            let key = u8::from_str_radix(key, 16)
+                .ok()
+                .filter(|&key| key < 16)
+                .ok_or_else(|| format!("Not a CHIP-8 key: {}", key))?;
+            for host in hosts {
+                keymap.bind(host, key);
+            }
+        }
+        Ok(keymap)
+    }
+
+    /// `None` if rewinding is turned off.
+    pub fn rewind_buffer(&self) -> Option<RewindBuffer> {
+        let seconds = self.rewind.seconds.unwrap_or_default() as usize;
+        let memory = self.rewind.memory.unwrap_or_default() as usize;
+        (seconds > 0).then(|| {
+            RewindBuffer::new(
+                seconds * chip_8_emulator::FRAME_RATE as usize,
+                memory * 1024 * 1024,
+            )
+        })
+    }
+
+    pub fn audio_settings(&self) -> AudioSettings {
+        let default = AudioSettings::default();
+        AudioSettings {
+            frequency: self.audio.frequency.unwrap_or(default.frequency),
+            volume: self.audio.volume.unwrap_or(default.volume).clamp(0.0, 1.0),
+            waveform: self.audio.waveform.unwrap_or(default.waveform),
+        }
+    }
+}
+
+/// Host keys for the actions of `Entry::keys`.
+const ACTION_KEYS: [(&str, [&str; 2]); 6] = [
+    ("up", ["Up", "Pad Up"]),
+    ("down", ["Down", "Pad Down"]),
+    ("left", ["Left", "Pad Left"]),
+    ("right", ["Right", "Pad Right"]),
+    ("a", ["Space", "Pad A"]),
+    ("b", ["Return", "Pad B"]),
+];
+
+/// The config file, e.g.
+///
+/// ```toml
+/// [default]
+/// ips = 700
+/// palette = "000000,ffffff"
+/// keymap = "azerty"
+///
+/// [default.audio]
+/// volume = 0.1
+///
+/// [default.rewind]
+/// seconds = 60
+///
+/// [default.screenshots]
+/// directory = "/home/me/Pictures"
+///
+/// [rom.a1b2...]   # SHA-1 of the ROM, as shown by `info`
+/// name = "Pong"
+/// quirks = "vip"
+/// ```
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub default: Settings,
+    pub rom: BTreeMap<String, Settings>,
+}
+
+impl Config {
+    /// Where the config is read from unless `--config` says otherwise,
+    /// `~/.config/chip_8_emulator/config.toml` on Linux.
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("chip_8_emulator").join("config.toml"))
+    }
+
+    /// Programs to add to the ROM database, in the same directory as the config.
+    pub fn programs_path(config_path: Option<&Path>) -> Option<PathBuf> {
+        let config_path = match config_path {
+            Some(path) => path.to_path_buf(),
+            None => Config::default_path()?,
+        };
+        Some(config_path.with_file_name("programs.json"))
+    }
+
+    /// Reads the config at `path`, or at the default path if `None`. Only a
+    /// missing default config is not an error.
+    pub fn load(path: Option<&Path>) -> Result<Config, String> {
+        let (path, required) = match path {
+            Some(path) => (path.to_path_buf(), true),
+            None => match Config::default_path() {
+                Some(path) => (path, false),
+                None => return Ok(Config::default()),
+            },
+        };
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == ErrorKind::NotFound && !required => {
+                return Ok(Config::default())
+            }
+            Err(err) => return Err(format!("Could not read {}: {}", path.display(), err)),
+        };
+        toml::from_str(&text).map_err(|err| format!("{}: {}", path.display(), err))
+    }
+
+    /// The section of the ROM, empty if there is none.
+    pub fn rom_settings(&self, sha1: &str) -> Settings {
+        self.rom
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(sha1))
+            .map(|(_, settings)| settings.clone())
+            .unwrap_or_default()
+    }
+}
+
+/// Stores options as the strings the command line takes, e.g. `quirks = "vip,no-clipping"`.
+mod as_text {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::fmt::Display;
+    use std::str::FromStr;
+
+    pub fn serialize<T: Display, S: Serializer>(
+        value: &Option<T>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(value) => serializer.collect_str(value),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+    where
+        T: FromStr<Err = String>,
+        D: Deserializer<'de>,
+    {
+        let text = String::deserialize(deserializer)?;
+        text.parse().map(Some).map_err(serde::de::Error::custom)
+    }
+}
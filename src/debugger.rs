@@ -0,0 +1,417 @@
+use crate::instruction::{decode, Instruction};
+use crate::{emulate, tick_timers, EmulatorError, Interpreter};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{self, BufRead, Write};
+
+/// A value the debugger can inspect.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Register {
+    V(u8),
+    I,
+    DelayTimer,
+    SoundTimer,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Condition {
+    pub register: Register,
+    pub comparison: Comparison,
+    pub value: u16,
+}
+
+/// Stops execution at `address`, when `condition` becomes true, or when both
+/// apply at once.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub address: Option<u16>,
+    pub condition: Option<Condition>,
+    was_met: bool,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Mode {
+    Running,
+    Paused,
+    Step,
+    /// Run until the stack is back to this depth, i.e. a `2NNN` call returned.
+    StepOver(usize),
+    /// Run until the stack is below this depth, i.e. the current subroutine returned.
+    RunToReturn(usize),
+}
+
+/// Interactive debugger reading commands from stdin. Drive the interpreter
+/// with `Debugger::run_frame` instead of `run_frame` to use it.
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    mode: Mode,
+    /// Set when resuming, so a breakpoint on the current address does not
+    /// trigger again right away.
+    resuming: bool,
+    /// Label names by address, e.g. from an Octo program.
+    labels: BTreeMap<u16, String>,
+    /// The user typed `quit`.
+    quit: bool,
+}
+
+impl Default for Debugger {
+    fn default() -> Debugger {
+        Debugger::new()
+    }
+}
+
+impl Debugger {
+    pub fn new() -> Debugger {
+        Debugger {
+            breakpoints: Vec::new(),
+            mode: Mode::Running,
+            resuming: false,
+            labels: BTreeMap::new(),
+            quit: false,
+        }
+    }
+
+    /// Stops before the next instruction and opens the prompt.
+    pub fn pause(&mut self) {
+        self.mode = Mode::Paused;
+    }
+
+    /// Whether `quit` was entered at the prompt. `run_frame` stops right
+    /// away then, ending the program is up to the caller.
+    pub fn has_quit(&self) -> bool {
+        self.quit
+    }
+
+    /// Shows these names for addresses and accepts them for breakpoints.
+    pub fn set_labels(&mut self, labels: &BTreeMap<String, u16>) {
+        self.labels = labels
+            .iter()
+            .map(|(name, &address)| (address, name.clone()))
+            .collect();
+    }
+
+    pub fn add_breakpoint(&mut self, address: Option<u16>, condition: Option<Condition>) {
+        self.breakpoints.push(Breakpoint {
+            address,
+            condition,
+            was_met: false,
+        });
+    }
+
+    /// Like `crate::run_frame`, but stops for breakpoints and steps.
+    pub fn run_frame(
+        &mut self,
+        interpreter: &mut Interpreter,
+        instructions_per_frame: u32,
+    ) -> Result<bool, EmulatorError> {
+        let mut redraw = false;
+        for _ in 0..instructions_per_frame {
+            if interpreter.exited {
+                break;
+            }
+            if self.should_stop(interpreter) {
+                self.prompt(interpreter);
+            }
+            if self.quit {
+                return Ok(redraw);
+            }
+            self.resuming = false;
+            match emulate(interpreter) {
+                Ok(changed) => redraw |= changed,
+                Err(err) => {
+                    println!("{}", err);
+                    self.prompt(interpreter);
+                    return Err(err);
+                }
+            }
+        }
+        tick_timers(interpreter);
+        Ok(redraw)
+    }
+
+    fn should_stop(&mut self, interpreter: &Interpreter) -> bool {
+        let depth = interpreter.cpu.stack.len();
+        let stop = match self.mode {
+            Mode::Running => false,
+            Mode::Paused | Mode::Step => true,
+            Mode::StepOver(target) => depth <= target,
+            Mode::RunToReturn(target) => depth < target,
+        };
+        // Conditions are checked even while stepping so they track their state.
+        let mut hit = false;
+        for breakpoint in self.breakpoints.iter_mut() {
+            let at_address = breakpoint
+                .address
+                .is_none_or(|address| address == interpreter.cpu.programm_counter);
+            let condition = breakpoint
+                .condition
+                .is_none_or(|condition| condition_holds(interpreter, condition));
+            let met = at_address && condition;
+            // Pure conditions only fire when they become true, not on every
+            // instruction they stay true for.
+            if met && (breakpoint.address.is_some() || !breakpoint.was_met) {
+                hit = true;
+            }
+            breakpoint.was_met = met;
+        }
+        stop || (hit && !self.resuming)
+    }
+
+    fn prompt(&mut self, interpreter: &mut Interpreter) {
+        self.mode = Mode::Paused;
+        print!("{}", describe(interpreter, &self.labels));
+        let stdin = io::stdin();
+        loop {
+            print!("(debug) ");
+            let _ = io::stdout().flush();
+            let mut line = String::new();
+            if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 {
+                // stdin closed, nobody can type a command anymore.
+                self.mode = Mode::Running;
+                return;
+            }
+            match self.command(interpreter, line.trim()) {
+                Ok(true) => {
+                    self.resuming = true;
+                    return;
+                }
+                Ok(false) => (),
+                Err(message) => println!("{}", message),
+            }
+        }
+    }
+
+    /// Runs one prompt command. Returns whether execution should continue.
+    pub fn command(&mut self, interpreter: &Interpreter, line: &str) -> Result<bool, String> {
+        let mut words = line.split_whitespace();
+        let command = words.next().unwrap_or("s");
+        let arguments: Vec<&str> = words.collect();
+        match command {
+            "c" | "continue" => self.mode = Mode::Running,
+            "s" | "step" => self.mode = Mode::Step,
+            "n" | "next" => {
+                let opcode = opcode_at(interpreter, interpreter.cpu.programm_counter);
+                self.mode = if let Some(Instruction::Call { .. }) = decode(opcode, 0) {
+                    Mode::StepOver(interpreter.cpu.stack.len())
+                } else {
+                    Mode::Step
+                };
+            }
+            "f" | "finish" => {
+                if interpreter.cpu.stack.is_empty() {
+                    return Err("Not inside a subroutine".to_string());
+                }
+                self.mode = Mode::RunToReturn(interpreter.cpu.stack.len());
+            }
+            "b" | "break" => {
+                let (address, condition) = parse_breakpoint(&arguments, &self.labels)?;
+                self.add_breakpoint(address, condition);
+                println!("Breakpoint {} set", self.breakpoints.len() - 1);
+                return Ok(false);
+            }
+            "d" | "delete" => {
+                let index: usize = arguments
+                    .first()
+                    .and_then(|index| index.parse().ok())
+                    .ok_or("Usage: delete <breakpoint number>")?;
+                if index >= self.breakpoints.len() {
+                    return Err(format!("No breakpoint {}", index));
+                }
+                self.breakpoints.remove(index);
+                return Ok(false);
+            }
+            "l" | "list" => {
+                for (index, breakpoint) in self.breakpoints.iter().enumerate() {
+                    println!("{}: {}", index, format_breakpoint(breakpoint));
+                }
+                return Ok(false);
+            }
+            "r" | "regs" => {
+                print!("{}", describe(interpreter, &self.labels));
+                return Ok(false);
+            }
+            "q" | "quit" => {
+                self.quit = true;
+                self.mode = Mode::Running;
+            }
+            "h" | "help" => {
+                println!(
+                    "c(ontinue)  s(tep)  n(ext, steps over calls)  f(inish, runs to return)\n\
+                     b(reak) [ADDR] [if REG OP VALUE]  d(elete) N  l(ist)  r(egs)  q(uit)\n\
+                     REG is V0-VF, I, DT or ST, OP one of == != < <= > >="
+                );
+                return Ok(false);
+            }
+            _ => return Err(format!("Unknown command {}, try help", command)),
+        }
+        Ok(true)
+    }
+}
+
+fn opcode_at(interpreter: &Interpreter, address: u16) -> u16 {
+    let address = address as usize;
+    if address + 1 >= interpreter.ram.len() {
+        return 0;
+    }
+    ((interpreter.ram[address] as u16) << 8) | interpreter.ram[address + 1] as u16
+}
+
+fn register_value(interpreter: &Interpreter, register: Register) -> u16 {
+    match register {
+        Register::V(index) => interpreter.cpu.gen_purpose_registers[index as usize] as u16,
+        Register::I => interpreter.cpu.i,
+        Register::DelayTimer => interpreter.cpu.delay_timer as u16,
+        Register::SoundTimer => interpreter.cpu.sound_timer as u16,
+    }
+}
+
+fn condition_holds(interpreter: &Interpreter, condition: Condition) -> bool {
+    let value = register_value(interpreter, condition.register);
+    match condition.comparison {
+        Comparison::Equal => value == condition.value,
+        Comparison::NotEqual => value != condition.value,
+        Comparison::Less => value < condition.value,
+        Comparison::LessOrEqual => value <= condition.value,
+        Comparison::Greater => value > condition.value,
+        Comparison::GreaterOrEqual => value >= condition.value,
+    }
+}
+
+fn parse_number(text: &str) -> Result<u16, String> {
+    let parsed = match text.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+    parsed.map_err(|_| format!("Not a number: {}", text))
+}
+
+fn parse_register(text: &str) -> Result<Register, String> {
+    match text.to_ascii_uppercase().as_str() {
+        "I" => Ok(Register::I),
+        "DT" => Ok(Register::DelayTimer),
+        "ST" => Ok(Register::SoundTimer),
+        name => name
+            .strip_prefix('V')
+            .filter(|index| index.len() == 1)
+            .and_then(|index| u8::from_str_radix(index, 16).ok())
+            .map(Register::V)
+            .ok_or(format!("Not a register: {}", text)),
+    }
+}
+
+fn parse_breakpoint(
+    arguments: &[&str],
+    labels: &BTreeMap<u16, String>,
+) -> Result<(Option<u16>, Option<Condition>), String> {
+    let (address, rest) = match arguments {
+        [first, rest @ ..] if *first != "if" => {
+            let label = labels.iter().find(|(_, name)| name == first);
+            let address = match label {
+                Some((&address, _)) => address,
+                None => parse_number(first)?,
+            };
+            (Some(address), rest)
+        }
+        _ => (None, arguments),
+    };
+    let condition = match rest {
+        [] => None,
+        ["if", register, comparison, value] => Some(Condition {
+            register: parse_register(register)?,
+            comparison: match *comparison {
+                "==" => Comparison::Equal,
+                "!=" => Comparison::NotEqual,
+                "<" => Comparison::Less,
+                "<=" => Comparison::LessOrEqual,
+                ">" => Comparison::Greater,
+                ">=" => Comparison::GreaterOrEqual,
+                _ => return Err(format!("Unknown comparison {}", comparison)),
+            },
+            value: parse_number(value)?,
+        }),
+        _ => return Err("Usage: break [ADDR] [if REG OP VALUE]".to_string()),
+    };
+    if address.is_none() && condition.is_none() {
+        return Err("Usage: break [ADDR] [if REG OP VALUE]".to_string());
+    }
+    Ok((address, condition))
+}
+
+fn format_breakpoint(breakpoint: &Breakpoint) -> String {
+    let mut text = String::new();
+    if let Some(address) = breakpoint.address {
+        let _ = write!(text, "{:#06x} ", address);
+    }
+    if let Some(condition) = breakpoint.condition {
+        let register = match condition.register {
+            Register::V(index) => format!("V{:X}", index),
+            Register::I => "I".to_string(),
+            Register::DelayTimer => "DT".to_string(),
+            Register::SoundTimer => "ST".to_string(),
+        };
+        let comparison = match condition.comparison {
+            Comparison::Equal => "==",
+            Comparison::NotEqual => "!=",
+            Comparison::Less => "<",
+            Comparison::LessOrEqual => "<=",
+            Comparison::Greater => ">",
+            Comparison::GreaterOrEqual => ">=",
+        };
+        let _ = write!(
+            text,
+            "if {} {} {:#x}",
+            register, comparison, condition.value
+        );
+    }
+    text
+}
+
+/// The instruction at the program counter followed by all registers.
+/// Addresses with an entry in `labels` are shown by name.
+pub fn describe(interpreter: &Interpreter, labels: &BTreeMap<u16, String>) -> String {
+    let cpu = &interpreter.cpu;
+    let opcode = opcode_at(interpreter, cpu.programm_counter);
+    let next = opcode_at(interpreter, cpu.programm_counter.wrapping_add(2));
+    let mnemonic = match decode(opcode, next) {
+        Some(instruction) => match instruction.target().and_then(|target| labels.get(&target)) {
+            Some(label) => instruction.format_with_label(label),
+            None => instruction.to_string(),
+        },
+        None => format!("DW {:#06x}", opcode),
+    };
+    let mut text = String::new();
+    if let Some(label) = labels.get(&cpu.programm_counter) {
+        let _ = writeln!(text, "{}:", label);
+    }
+    let _ = writeln!(
+        text,
+        "{:#06x}: {:04x}  {}",
+        cpu.programm_counter, opcode, mnemonic
+    );
+    for (index, value) in cpu.gen_purpose_registers.iter().enumerate() {
+        let _ = write!(text, "V{:X}={:02x}", index, value);
+        text.push(if index % 8 == 7 { '\n' } else { ' ' });
+    }
+    let _ = writeln!(
+        text,
+        "I={:#06x} DT={:02x} ST={:02x}",
+        cpu.i, cpu.delay_timer, cpu.sound_timer
+    );
+    let stack: Vec<String> = cpu
+        .stack
+        .iter()
+        .map(|address| format!("{:#06x}", address))
+        .collect();
+    let _ = writeln!(text, "Stack: [{}]", stack.join(", "));
+    text
+}
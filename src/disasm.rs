@@ -0,0 +1,143 @@
+use crate::instruction::{decode, Instruction};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Write;
+
+/// One line of a disassembly: an instruction, or data that does not decode.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Line {
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    pub instruction: Option<Instruction>,
+}
+
+/// Decodes `rom` front to back as if it was loaded at `origin`. Everything
+/// is treated as code, sprites and other data come out as the instructions
+/// they happen to look like or as `DW`/`DB`.
+pub fn decode_rom(rom: &[u8], origin: u16) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut offset = 0;
+    while offset < rom.len() {
+        let word = |offset: usize| {
+            rom.get(offset..offset + 2)
+                .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+        };
+        let instruction = word(offset).and_then(|opcode| match decode(opcode, 0) {
+            // The long address has to be part of the ROM as well.
+            Some(Instruction::LoadLongIndex { .. }) => {
+                word(offset + 2).map(|address| Instruction::LoadLongIndex { address })
+            }
+            instruction => instruction,
+        });
+        let len = match instruction {
+            Some(instruction) => instruction.size() as usize,
+            None => 2.min(rom.len() - offset),
+        };
+        lines.push(Line {
+            address: origin.wrapping_add(offset as u16),
+            bytes: rom[offset..offset + len].to_vec(),
+            instruction,
+        });
+        offset += len;
+    }
+    lines
+}
+
+/// Names for the addresses jumped to, called or loaded into `I`. Only
+/// addresses a line starts at get one, so every label can be placed.
+pub fn labels(lines: &[Line]) -> BTreeMap<u16, String> {
+    let starts: HashSet<u16> = lines.iter().map(|line| line.address).collect();
+    // Lower ranks win when an address is used in several ways.
+    let mut ranked: BTreeMap<u16, u8> = BTreeMap::new();
+    for instruction in lines.iter().filter_map(|line| line.instruction) {
+        let rank = match instruction {
+            Instruction::Call { .. } => 0,
+            Instruction::Jump { .. } | Instruction::JumpOffset { .. } => 1,
+            _ => 2,
+        };
+        if let Some(target) = instruction
+            .target()
+            .filter(|target| starts.contains(target))
+        {
+            let entry = ranked.entry(target).or_insert(rank);
+            *entry = (*entry).min(rank);
+        }
+    }
+    ranked
+        .into_iter()
+        .map(|(address, rank)| {
+            let prefix = ["sub", "label", "data"][rank as usize];
+            (address, format!("{}_{:03x}", prefix, address))
+        })
+        .collect()
+}
+
+/// Disassembles `rom` into a listing that the assembler reads back into the
+/// same bytes. Addresses and raw bytes are in the comment column.
+pub fn disassemble(rom: &[u8], origin: u16) -> String {
+    let lines = decode_rom(rom, origin);
+    let labels = labels(&lines);
+    let mut text = String::new();
+    for line in &lines {
+        if let Some(label) = labels.get(&line.address) {
+            let _ = writeln!(text, "{}:", label);
+        }
+        let source = match line.instruction {
+            Some(instruction) => {
+                match instruction.target().and_then(|target| labels.get(&target)) {
+                    Some(label) => instruction.format_with_label(label),
+                    None => instruction.to_string(),
+                }
+            }
+            None if line.bytes.len() == 2 => {
+                format!(
+                    "DW {:#06x}",
+                    u16::from_be_bytes([line.bytes[0], line.bytes[1]])
+                )
+            }
+            None => format!("DB {:#04x}", line.bytes[0]),
+        };
+        let bytes: Vec<String> = line
+            .bytes
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        let _ = writeln!(
+            text,
+            "    {:<24}; {:#06x}: {}",
+            source,
+            line.address,
+            bytes.join(" ")
+        );
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+    use crate::PROGRAM_START;
+
+    fn round_trip(rom: &[u8]) {
+        let listing = disassemble(rom, PROGRAM_START);
+        let assembled = assemble(&listing).unwrap_or_else(|err| panic!("{}\n{}", err, listing));
+        assert_eq!(assembled, rom, "{}", listing);
+    }
+
+    #[test]
+    fn every_opcode_assembles_back() {
+        for opcode in 0..=u16::MAX {
+            // Followed by a word for the long load and a jump target.
+            let [high, low] = opcode.to_be_bytes();
+            round_trip(&[high, low, 0x12, 0x34]);
+        }
+    }
+
+    #[test]
+    fn bundled_roms_assemble_back() {
+        round_trip(include_bytes!("../games/pong.rom"));
+        round_trip(include_bytes!("../games/RPS.ch8"));
+        // An odd length ends in a single byte.
+        round_trip(&[0x00, 0xE0, 0xAB]);
+    }
+}
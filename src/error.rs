@@ -0,0 +1,136 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// A fault raised by a program. `address` is where the faulting instruction
+/// is stored.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EmulatorError {
+    UnknownOpcode { opcode: u16, address: u16 },
+    StackUnderflow { address: u16 },
+    StackOverflow { address: u16 },
+    MemoryOutOfBounds { index: usize, address: u16 },
+    ProgramCounterOutOfBounds { address: u16 },
+}
+
+/// What `emulate` does when an instruction faults.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// Stop on the faulting instruction and return the error.
+    #[default]
+    Halt,
+    /// Add the error to `Interpreter::skipped_errors` and continue after the
+    /// whole instruction, 4 bytes for `F000 NNNN`.
+    Skip,
+    /// Silently treat the faulting word as a 2 byte no-op and continue with
+    /// the word after it, even if that is the operand of `F000 NNNN`.
+    Nop,
+}
+
+impl ErrorPolicy {
+    pub fn from_name(name: &str) -> Option<ErrorPolicy> {
+        match name.to_ascii_lowercase().as_str() {
+            "halt" => Some(ErrorPolicy::Halt),
+            "skip" => Some(ErrorPolicy::Skip),
+            "nop" => Some(ErrorPolicy::Nop),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ErrorPolicy::Halt => "halt",
+            ErrorPolicy::Skip => "skip",
+            ErrorPolicy::Nop => "nop",
+        }
+    }
+}
+
+impl fmt::Display for ErrorPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for ErrorPolicy {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<ErrorPolicy, String> {
+        ErrorPolicy::from_name(name).ok_or_else(|| format!("Unknown error policy {}", name))
+    }
+}
+
+impl fmt::Display for EmulatorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmulatorError::UnknownOpcode { opcode, address } => {
+                write!(f, "Invalid Instruction {:#06x} at {:#06x}", opcode, address)
+            }
+            EmulatorError::StackUnderflow { address } => {
+                write!(f, "Return with empty stack at {:#06x}", address)
+            }
+            EmulatorError::StackOverflow { address } => {
+                write!(f, "Call with full stack at {:#06x}", address)
+            }
+            EmulatorError::MemoryOutOfBounds { index, address } => write!(
+                f,
+                "Memory access at {:#06x} out of bounds at {:#06x}",
+                index, address
+            ),
+            EmulatorError::ProgramCounterOutOfBounds { address } => {
+                write!(f, "Program counter {:#06x} is outside of memory", address)
+            }
+        }
+    }
+}
+
+impl Error for EmulatorError {}
+
+/// Why a save state could not be loaded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StateError {
+    NotASaveState,
+    UnsupportedVersion(u8),
+    VariantMismatch,
+    Truncated,
+    Corrupted,
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateError::NotASaveState => write!(f, "Not a save state"),
+            StateError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported save state version {}", version)
+            }
+            StateError::VariantMismatch => {
+                write!(f, "Save state was made for a different CHIP-8 variant")
+            }
+            StateError::Truncated => write!(f, "Save state is truncated"),
+            StateError::Corrupted => write!(f, "Save state is corrupted"),
+        }
+    }
+}
+
+impl Error for StateError {}
+
+/// A problem in assembler source, `line` and `column` count from 1.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AsmError {
+    /// The file the line is in, `None` for the source passed to `assemble`.
+    pub file: Option<String>,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{}:", file)?;
+        }
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl Error for AsmError {}
@@ -0,0 +1,475 @@
+use crate::audio::{AudioPattern, AudioSettings, ToneGenerator};
+use crate::{Screen, FRAME_RATE};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// RGB colors for a pixel lit on no plane, plane 1, plane 2 and both planes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Palette(pub [[u8; 3]; 4]);
+
+impl Default for Palette {
+    fn default() -> Palette {
+        Palette([[0, 0, 0], [255, 255, 255], [255, 102, 0], [102, 34, 0]])
+    }
+}
+
+impl Palette {
+    pub fn color(&self, planes: u8) -> [u8; 3] {
+        self.0[(planes & 0x3) as usize]
+    }
+
+    /// Parses two to four comma separated hex colors like `000000,ffffff`.
+    /// The colors for pixels on the second plane keep their defaults if left out.
+    pub fn parse(text: &str) -> Result<Palette, String> {
+        let mut palette = Palette::default();
+        let colors: Vec<&str> = text.split(',').map(str::trim).collect();
+        if colors.len() < 2 || colors.len() > 4 {
+            return Err(format!("Expected 2 to 4 colors but got {}", colors.len()));
+        }
+        for (slot, color) in palette.0.iter_mut().zip(colors) {
+            let hex = color.strip_prefix('#').unwrap_or(color);
+            let value = u32::from_str_radix(hex, 16)
+                .ok()
+                .filter(|_| hex.len() == 6)
+                .ok_or_else(|| format!("Not a hex color: {}", color))?;
+            *slot = [(value >> 16) as u8, (value >> 8) as u8, value as u8];
+        }
+        Ok(palette)
+    }
+}
+
+/// The four colors as hex, in the form `Palette::parse` reads.
+impl fmt::Display for Palette {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let colors: Vec<String> = self
+            .0
+            .iter()
+            .map(|[r, g, b]| format!("{:02x}{:02x}{:02x}", r, g, b))
+            .collect();
+        write!(f, "{}", colors.join(","))
+    }
+}
+
+impl FromStr for Palette {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Palette, String> {
+        Palette::parse(text)
+    }
+}
+
+/// Which host keys press which CHIP-8 key. Host keys go by the names SDL
+/// gives them, e.g. `x`, `Keypad 7` or `Up`, and any number of them can press
+/// the same CHIP-8 key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Keymap {
+    bindings: Vec<(String, u8)>,
+}
+
+impl Default for Keymap {
+    fn default() -> Keymap {
+        Keymap::preset("qwerty").unwrap()
+    }
+}
+
+impl Keymap {
+    pub const PRESETS: [&'static str; 6] = [
+        "qwerty",
+        "vip",
+        "cosmac-vip",
+        "azerty",
+        "numpad",
+        "hex-labels",
+    ];
+
+    pub fn preset(name: &str) -> Option<Keymap> {
+        let keymap = match name.to_ascii_lowercase().as_str() {
+            // The hex keypad on the left of the keyboard, in the same spots
+            // as on the COSMAC VIP.
+            "qwerty" | "vip" | "cosmac-vip" => Keymap::layout("x123qweasdzc4rfv"),
+            "azerty" => {
+                let mut keymap = Keymap::layout("x&é\"azeqsdwc'rfv");
+                // With shift or caps lock the digit row is read as digits.
+                for (host, key) in [("1", 0x1), ("2", 0x2), ("3", 0x3), ("4", 0xC)] {
+                    keymap.bind(host, key);
+                }
+                keymap
+            }
+            "numpad" => {
+                let mut keymap = Keymap::empty();
+                for key in 0..10 {
+                    keymap.bind(&format!("Keypad {}", key), key);
+                }
+                let extra = ["Keypad /", "Keypad *", "Keypad -", "Keypad +"];
+                for (host, key) in extra.into_iter().zip(0xA..) {
+                    keymap.bind(host, key);
+                }
+                keymap.bind("Keypad Enter", 0xE);
+                keymap.bind("Keypad .", 0xF);
+                keymap
+            }
+            // Each CHIP-8 key is the host key with the same hex label.
+            "hex-labels" => Keymap::layout("0123456789abcdef"),
+            _ => return None,
+        };
+        Some(keymap)
+    }
+
+    pub fn empty() -> Keymap {
+        Keymap {
+            bindings: Vec::new(),
+        }
+    }
+
+    /// A keymap from one character per CHIP-8 key, in order from 0 to F.
+    fn layout(keys: &str) -> Keymap {
+        let mut keymap = Keymap::empty();
+        for (key, host) in keys.chars().enumerate() {
+            keymap.bind(&host.to_string(), key as u8);
+        }
+        keymap
+    }
+
+    /// Parses a preset name or 16 keys for CHIP-8 keys 0 to F, like `x123qweasdzc4rfv`.
+    pub fn parse(spec: &str) -> Result<Keymap, String> {
+        if let Some(keymap) = Keymap::preset(spec) {
+            return Ok(keymap);
+        }
+        match spec.chars().count() {
+            16 => Ok(Keymap::layout(spec)),
+            _ => Err(format!(
+                "Expected a keymap preset ({}) or 16 keys, got {}",
+                Keymap::PRESETS.join(", "),
+                spec
+            )),
+        }
+    }
+
+    /// Makes `host` press CHIP-8 key `key` as well, on top of the keys already bound.
+    pub fn bind(&mut self, host: &str, key: u8) {
+        let binding = (host.to_string(), key & 0xF);
+        if !self.bindings.contains(&binding) {
+            self.bindings.push(binding);
+        }
+    }
+
+    pub fn bindings(&self) -> impl Iterator<Item = (&str, u8)> {
+        self.bindings
+            .iter()
+            .map(|(host, key)| (host.as_str(), *key))
+    }
+}
+
+/// Something that can show the framebuffer.
+pub trait Display {
+    fn draw(&mut self, screen: &Screen);
+
+    /// A short line about the emulator, like the frame a movie is at, shown
+    /// with the next `draw`. It is not part of the screen, so screenshots and
+    /// GIFs stay clean.
+    fn show_status(&mut self, _status: &str) {}
+}
+
+/// The pixels of `text` in a 3x5 font, one column between characters.
+/// Characters missing from the font are left blank.
+pub fn text_pixels(text: &str) -> Vec<(usize, usize)> {
+    let mut pixels = Vec::new();
+    for (index, c) in text.chars().enumerate() {
+        let rows = glyph(c).unwrap_or_default();
+        for (y, row) in rows.into_iter().enumerate() {
+            for x in (0..3).filter(|x| row & (0b100 >> x) != 0) {
+                pixels.push((index * 4 + x, y));
+            }
+        }
+    }
+    pixels
+}
+
+/// Rows of a character, the leftmost pixel in bit 2.
+fn glyph(c: char) -> Option<[u8; 5]> {
+    let rows = match c.to_ascii_uppercase() {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        _ => return None,
+    };
+    Some(rows)
+}
+
+/// Emulator controls a frontend can ask for besides CHIP-8 key presses.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Hotkey {
+    /// The window was closed or the user asked to quit.
+    Quit,
+    ToggleMute,
+    SaveState,
+    LoadState,
+    NextSlot,
+    PreviousSlot,
+    Debug,
+    TogglePause,
+    /// Run a single frame while paused.
+    FrameAdvance,
+    /// Restart the program that is loaded.
+    Reset,
+    /// Read the program from disk again and restart it.
+    Reload,
+    /// Run faster while the key is held, `false` once it is released.
+    FastForward(bool),
+    /// Step back through the last frames while the key is held, `false` once
+    /// it is released.
+    Rewind(bool),
+    ToggleSlowMotion,
+    Screenshot,
+    /// Start or stop recording a GIF.
+    ToggleCapture,
+}
+
+/// Something that reports which of the 16 keys are held down.
+pub trait Keypad {
+    fn poll(&mut self, keys: &mut [bool; 16]) -> Vec<Hotkey>;
+}
+
+/// Something that can play the buzzer while the sound timer runs. Called once
+/// per emulated frame.
+pub trait Audio {
+    fn set_beeping(&mut self, beeping: bool);
+
+    /// What the buzzer plays, see `audio_pattern`. Called before `set_beeping`.
+    fn set_pattern(&mut self, _pattern: Option<AudioPattern>) {}
+}
+
+/// Frontend that ignores all output and never presses a key.
+pub struct NullFrontend;
+
+impl Display for NullFrontend {
+    fn draw(&mut self, _screen: &Screen) {}
+}
+
+impl Keypad for NullFrontend {
+    fn poll(&mut self, _keys: &mut [bool; 16]) -> Vec<Hotkey> {
+        Vec::new()
+    }
+}
+
+impl Audio for NullFrontend {
+    fn set_beeping(&mut self, _beeping: bool) {}
+}
+
+/// Records when the buzzer starts and stops instead of playing it.
+#[derive(Clone, Debug, Default)]
+pub struct AudioRecorder {
+    frame: u64,
+    beeping: bool,
+    changes: Vec<(u64, bool)>,
+    pattern: Option<AudioPattern>,
+    pattern_changes: Vec<(u64, Option<AudioPattern>)>,
+}
+
+impl AudioRecorder {
+    pub fn new() -> AudioRecorder {
+        AudioRecorder::default()
+    }
+
+    /// The frames on which the buzzer was switched on or off.
+    pub fn changes(&self) -> &[(u64, bool)] {
+        &self.changes
+    }
+
+    /// Renders the recorded buzzer as mono samples at `sample_rate`.
+    pub fn samples(&self, settings: AudioSettings, sample_rate: u32) -> Vec<f32> {
+        let mut generator = ToneGenerator::new(settings);
+        let mut samples = Vec::new();
+        let mut changes = self.changes.iter().peekable();
+        let mut pattern_changes = self.pattern_changes.iter().peekable();
+        for frame in 0..self.frame {
+            while let Some(&(_, beeping)) = changes.next_if(|(at, _)| *at == frame) {
+                generator.set_beeping(beeping);
+            }
+            while let Some(&(_, pattern)) = pattern_changes.next_if(|(at, _)| *at == frame) {
+                generator.set_pattern(pattern);
+            }
+            let start = samples.len();
+            let end = ((frame + 1) * sample_rate as u64 / FRAME_RATE as u64) as usize;
+            samples.resize(end, 0.0);
+            generator.fill(&mut samples[start..], sample_rate);
+        }
+        samples
+    }
+}
+
+impl Audio for AudioRecorder {
+    fn set_beeping(&mut self, beeping: bool) {
+        if beeping != self.beeping {
+            self.changes.push((self.frame, beeping));
+            self.beeping = beeping;
+        }
+        self.frame += 1;
+    }
+
+    fn set_pattern(&mut self, pattern: Option<AudioPattern>) {
+        if pattern != self.pattern {
+            self.pattern_changes.push((self.frame, pattern));
+            self.pattern = pattern;
+        }
+    }
+}
+
+/// Draws the screen to a terminal using half block characters, so two
+/// pixel rows fit into one line of text.
+pub struct TerminalDisplay<W: Write> {
+    out: W,
+    /// Printed below the screen.
+    status: String,
+}
+
+impl TerminalDisplay<io::Stdout> {
+    pub fn stdout() -> TerminalDisplay<io::Stdout> {
+        TerminalDisplay::new(io::stdout())
+    }
+}
+
+impl<W: Write> TerminalDisplay<W> {
+    pub fn new(out: W) -> TerminalDisplay<W> {
+        TerminalDisplay {
+            out,
+            status: String::new(),
+        }
+    }
+}
+
+impl<W: Write> Display for TerminalDisplay<W> {
+    fn draw(&mut self, screen: &Screen) {
+        let mut frame = String::from("\x1b[H");
+        for y in (0..screen.height()).step_by(2) {
+            for x in 0..screen.width() {
+                let top = screen.is_lit(x, y);
+                let bottom = y + 1 < screen.height() && screen.is_lit(x, y + 1);
+                frame.push(match (top, bottom) {
+                    (true, true) => '█',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (false, false) => ' ',
+                });
+            }
+            frame.push('\n');
+        }
+        // Cleared to the end of the line, the last status may have been longer.
+        frame.push_str(&self.status);
+        frame.push_str("\x1b[K\n");
+        let _ = self.out.write_all(frame.as_bytes());
+        let _ = self.out.flush();
+    }
+
+    fn show_status(&mut self, status: &str) {
+        self.status = status.to_string();
+    }
+}
+
+/// Writes every drawn frame as a numbered PBM image into a directory.
+pub struct ImageDumpDisplay {
+    directory: PathBuf,
+    frame: u64,
+}
+
+impl ImageDumpDisplay {
+    pub fn new(directory: impl Into<PathBuf>) -> ImageDumpDisplay {
+        ImageDumpDisplay {
+            directory: directory.into(),
+            frame: 0,
+        }
+    }
+
+    fn write_frame(&self, screen: &Screen) -> io::Result<()> {
+        let path = self.directory.join(format!("frame_{:06}.pbm", self.frame));
+        let mut file = File::create(path)?;
+        writeln!(file, "P1\n{} {}", screen.width(), screen.height())?;
+        for y in 0..screen.height() {
+            let row: Vec<&str> = (0..screen.width())
+                .map(|x| if screen.is_lit(x, y) { "1" } else { "0" })
+                .collect();
+            writeln!(file, "{}", row.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for ImageDumpDisplay {
+    fn draw(&mut self, screen: &Screen) {
+        if let Err(err) = self.write_frame(screen) {
+            eprintln!("Could not dump frame {}: {}", self.frame, err);
+        }
+        self.frame += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vip_presets_are_the_qwerty_grid() {
+        for name in Keymap::PRESETS {
+            assert!(Keymap::preset(name).is_some(), "{}", name);
+        }
+        let qwerty = Keymap::preset("qwerty");
+        assert_eq!(Keymap::preset("vip"), qwerty);
+        assert_eq!(Keymap::preset("cosmac-vip"), qwerty);
+    }
+
+    #[test]
+    fn text_pixels_leave_a_column_between_characters() {
+        assert_eq!(text_pixels("1").len(), 8);
+        let pixels = text_pixels("-?-");
+        assert_eq!(pixels, [(0, 2), (1, 2), (2, 2), (8, 2), (9, 2), (10, 2)]);
+    }
+
+    #[test]
+    fn terminal_prints_the_status_below_the_screen() {
+        let mut display = TerminalDisplay::new(Vec::new());
+        display.show_status("frame 7");
+        display.draw(&Screen::new());
+        let text = String::from_utf8(display.out).unwrap();
+        assert!(text.ends_with("\nframe 7\x1b[K\n"), "{:?}", text);
+    }
+}
@@ -0,0 +1,368 @@
+use crate::Variant;
+use std::fmt;
+
+/// One decoded instruction of CHIP-8 and its SUPER-CHIP and XO-CHIP
+/// extensions. `x` and `y` are register numbers.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    /// `00E0`
+    ClearScreen,
+    /// `00EE`
+    Return,
+    /// `00CN`
+    ScrollDown { n: u8 },
+    /// `00FB`
+    ScrollRight,
+    /// `00FC`
+    ScrollLeft,
+    /// `00FD`
+    Exit,
+    /// `00FE`
+    LowRes,
+    /// `00FF`
+    HighRes,
+    /// `1NNN`
+    Jump { address: u16 },
+    /// `2NNN`
+    Call { address: u16 },
+    /// `3XNN`
+    SkipEqualImmediate { x: u8, value: u8 },
+    /// `4XNN`
+    SkipNotEqualImmediate { x: u8, value: u8 },
+    /// `5XY0`
+    SkipEqual { x: u8, y: u8 },
+    /// `5XY2`
+    SaveRange { x: u8, y: u8 },
+    /// `5XY3`
+    LoadRange { x: u8, y: u8 },
+    /// `6XNN`
+    LoadImmediate { x: u8, value: u8 },
+    /// `7XNN`
+    AddImmediate { x: u8, value: u8 },
+    /// `8XY0`
+    Move { x: u8, y: u8 },
+    /// `8XY1`
+    Or { x: u8, y: u8 },
+    /// `8XY2`
+    And { x: u8, y: u8 },
+    /// `8XY3`
+    Xor { x: u8, y: u8 },
+    /// `8XY4`
+    AddReg { x: u8, y: u8 },
+    /// `8XY5`
+    Sub { x: u8, y: u8 },
+    /// `8XY6`
+    ShiftRight { x: u8, y: u8 },
+    /// `8XY7`
+    SubReverse { x: u8, y: u8 },
+    /// `8XYE`
+    ShiftLeft { x: u8, y: u8 },
+    /// `9XY0`
+    SkipNotEqual { x: u8, y: u8 },
+    /// `ANNN`
+    LoadIndex { address: u16 },
+    /// `BNNN`, `x` is the top nibble of NNN for the jump-with-VX quirk.
+    JumpOffset { x: u8, address: u16 },
+    /// `CXNN`
+    Random { x: u8, mask: u8 },
+    /// `DXYN`
+    Draw { x: u8, y: u8, n: u8 },
+    /// `EX9E`
+    SkipKeyPressed { x: u8 },
+    /// `EXA1`
+    SkipKeyNotPressed { x: u8 },
+    /// `F000 NNNN`, the only instruction taking four bytes.
+    LoadLongIndex { address: u16 },
+    /// `FN01`
+    SelectPlanes { planes: u8 },
+    /// `F002`
+    LoadAudioPattern,
+    /// `FX07`
+    GetDelay { x: u8 },
+    /// `FX0A`
+    WaitKey { x: u8 },
+    /// `FX15`
+    SetDelay { x: u8 },
+    /// `FX18`
+    SetSound { x: u8 },
+    /// `FX1E`
+    AddIndex { x: u8 },
+    /// `FX29`
+    Font { x: u8 },
+    /// `FX30`
+    BigFont { x: u8 },
+    /// `FX33`
+    Bcd { x: u8 },
+    /// `FX3A`
+    Pitch { x: u8 },
+    /// `FX55`
+    Store { x: u8 },
+    /// `FX65`
+    Load { x: u8 },
+    /// `FX75`
+    SaveFlags { x: u8 },
+    /// `FX85`
+    LoadFlags { x: u8 },
+}
+
+impl Instruction {
+    /// Size of the instruction in bytes.
+    pub fn size(&self) -> u16 {
+        match self {
+            Instruction::LoadLongIndex { .. } => 4,
+            _ => 2,
+        }
+    }
+
+    /// The bytes of the instruction, the inverse of `decode`.
+    pub fn encode(&self) -> Vec<u8> {
+        let xy = |prefix: u16, x: u8, y: u8, n: u16| {
+            prefix << 12 | (x as u16 & 0xF) << 8 | (y as u16 & 0xF) << 4 | n
+        };
+        let xnn = |prefix: u16, x: u8, nn: u8| prefix << 12 | (x as u16 & 0xF) << 8 | nn as u16;
+        let fx = |x: u8, nn: u16| 0xF000 | (x as u16 & 0xF) << 8 | nn;
+        let opcode = match *self {
+            Instruction::ClearScreen => 0x00E0,
+            Instruction::Return => 0x00EE,
+            Instruction::ScrollDown { n } => 0x00C0 | (n as u16 & 0xF),
+            Instruction::ScrollRight => 0x00FB,
+            Instruction::ScrollLeft => 0x00FC,
+            Instruction::Exit => 0x00FD,
+            Instruction::LowRes => 0x00FE,
+            Instruction::HighRes => 0x00FF,
+            Instruction::Jump { address } => 0x1000 | (address & 0xFFF),
+            Instruction::Call { address } => 0x2000 | (address & 0xFFF),
+            Instruction::SkipEqualImmediate { x, value } => xnn(0x3, x, value),
+            Instruction::SkipNotEqualImmediate { x, value } => xnn(0x4, x, value),
+            Instruction::SkipEqual { x, y } => xy(0x5, x, y, 0x0),
+            Instruction::SaveRange { x, y } => xy(0x5, x, y, 0x2),
+            Instruction::LoadRange { x, y } => xy(0x5, x, y, 0x3),
+            Instruction::LoadImmediate { x, value } => xnn(0x6, x, value),
+            Instruction::AddImmediate { x, value } => xnn(0x7, x, value),
+            Instruction::Move { x, y } => xy(0x8, x, y, 0x0),
+            Instruction::Or { x, y } => xy(0x8, x, y, 0x1),
+            Instruction::And { x, y } => xy(0x8, x, y, 0x2),
+            Instruction::Xor { x, y } => xy(0x8, x, y, 0x3),
+            Instruction::AddReg { x, y } => xy(0x8, x, y, 0x4),
+            Instruction::Sub { x, y } => xy(0x8, x, y, 0x5),
+            Instruction::ShiftRight { x, y } => xy(0x8, x, y, 0x6),
+            Instruction::SubReverse { x, y } => xy(0x8, x, y, 0x7),
+            Instruction::ShiftLeft { x, y } => xy(0x8, x, y, 0xE),
+            Instruction::SkipNotEqual { x, y } => xy(0x9, x, y, 0x0),
+            Instruction::LoadIndex { address } => 0xA000 | (address & 0xFFF),
+            // X is part of the address, it only selects a register with a quirk.
+            Instruction::JumpOffset { address, .. } => 0xB000 | (address & 0xFFF),
+            Instruction::Random { x, mask } => xnn(0xC, x, mask),
+            Instruction::Draw { x, y, n } => xy(0xD, x, y, n as u16 & 0xF),
+            Instruction::SkipKeyPressed { x } => xnn(0xE, x, 0x9E),
+            Instruction::SkipKeyNotPressed { x } => xnn(0xE, x, 0xA1),
+            Instruction::LoadLongIndex { address } => {
+                let [high, low] = address.to_be_bytes();
+                return vec![0xF0, 0x00, high, low];
+            }
+            Instruction::SelectPlanes { planes } => fx(planes, 0x01),
+            Instruction::LoadAudioPattern => 0xF002,
+            Instruction::GetDelay { x } => fx(x, 0x07),
+            Instruction::WaitKey { x } => fx(x, 0x0A),
+            Instruction::SetDelay { x } => fx(x, 0x15),
+            Instruction::SetSound { x } => fx(x, 0x18),
+            Instruction::AddIndex { x } => fx(x, 0x1E),
+            Instruction::Font { x } => fx(x, 0x29),
+            Instruction::BigFont { x } => fx(x, 0x30),
+            Instruction::Bcd { x } => fx(x, 0x33),
+            Instruction::Pitch { x } => fx(x, 0x3A),
+            Instruction::Store { x } => fx(x, 0x55),
+            Instruction::Load { x } => fx(x, 0x65),
+            Instruction::SaveFlags { x } => fx(x, 0x75),
+            Instruction::LoadFlags { x } => fx(x, 0x85),
+        };
+        opcode.to_be_bytes().to_vec()
+    }
+
+    /// Whether programs for `variant` may use this instruction.
+    pub fn is_supported_by(&self, variant: Variant) -> bool {
+        match self {
+            Instruction::ScrollDown { .. }
+            | Instruction::ScrollRight
+            | Instruction::ScrollLeft
+            | Instruction::Exit
+            | Instruction::LowRes
+            | Instruction::HighRes
+            | Instruction::BigFont { .. }
+            | Instruction::SaveFlags { .. }
+            | Instruction::LoadFlags { .. } => variant.has_superchip_instructions(),
+            Instruction::SaveRange { .. }
+            | Instruction::LoadRange { .. }
+            | Instruction::LoadLongIndex { .. }
+            | Instruction::SelectPlanes { .. }
+            | Instruction::LoadAudioPattern
+            | Instruction::Pitch { .. } => variant.has_xochip_instructions(),
+            _ => true,
+        }
+    }
+}
+
+/// Decodes `opcode`. `next` is the word after it, which is only used as the
+/// address of `F000 NNNN`. Returns `None` for opcodes no variant knows.
+pub fn decode(opcode: u16, next: u16) -> Option<Instruction> {
+    let x = ((opcode & 0x0F00) >> 8) as u8;
+    let y = ((opcode & 0x00F0) >> 4) as u8;
+    let n = (opcode & 0x000F) as u8;
+    let nn = (opcode & 0x00FF) as u8;
+    let nnn = opcode & 0x0FFF;
+    let instruction = match opcode >> 12 {
+        0x0 => match nnn {
+            0x0E0 => Instruction::ClearScreen,
+            0x0EE => Instruction::Return,
+            0x0C0..=0x0CF => Instruction::ScrollDown { n },
+            0x0FB => Instruction::ScrollRight,
+            0x0FC => Instruction::ScrollLeft,
+            0x0FD => Instruction::Exit,
+            0x0FE => Instruction::LowRes,
+            0x0FF => Instruction::HighRes,
+            _ => return None,
+        },
+        0x1 => Instruction::Jump { address: nnn },
+        0x2 => Instruction::Call { address: nnn },
+        0x3 => Instruction::SkipEqualImmediate { x, value: nn },
+        0x4 => Instruction::SkipNotEqualImmediate { x, value: nn },
+        0x5 => match n {
+            0x0 => Instruction::SkipEqual { x, y },
+            0x2 => Instruction::SaveRange { x, y },
+            0x3 => Instruction::LoadRange { x, y },
+            _ => return None,
+        },
+        0x6 => Instruction::LoadImmediate { x, value: nn },
+        0x7 => Instruction::AddImmediate { x, value: nn },
+        0x8 => match n {
+            0x0 => Instruction::Move { x, y },
+            0x1 => Instruction::Or { x, y },
+            0x2 => Instruction::And { x, y },
+            0x3 => Instruction::Xor { x, y },
+            0x4 => Instruction::AddReg { x, y },
+            0x5 => Instruction::Sub { x, y },
+            0x6 => Instruction::ShiftRight { x, y },
+            0x7 => Instruction::SubReverse { x, y },
+            0xE => Instruction::ShiftLeft { x, y },
+            _ => return None,
+        },
+        0x9 if n == 0 => Instruction::SkipNotEqual { x, y },
+        0xA => Instruction::LoadIndex { address: nnn },
+        0xB => Instruction::JumpOffset { x, address: nnn },
+        0xC => Instruction::Random { x, mask: nn },
+        0xD => Instruction::Draw { x, y, n },
+        0xE => match nn {
+            0x9E => Instruction::SkipKeyPressed { x },
+            0xA1 => Instruction::SkipKeyNotPressed { x },
+            _ => return None,
+        },
+        0xF => match nn {
+            0x00 if x == 0 => Instruction::LoadLongIndex { address: next },
+            0x01 => Instruction::SelectPlanes { planes: x },
+            0x02 if x == 0 => Instruction::LoadAudioPattern,
+            0x07 => Instruction::GetDelay { x },
+            0x0A => Instruction::WaitKey { x },
+            0x15 => Instruction::SetDelay { x },
+            0x18 => Instruction::SetSound { x },
+            0x1E => Instruction::AddIndex { x },
+            0x29 => Instruction::Font { x },
+            0x30 => Instruction::BigFont { x },
+            0x33 => Instruction::Bcd { x },
+            0x3A => Instruction::Pitch { x },
+            0x55 => Instruction::Store { x },
+            0x65 => Instruction::Load { x },
+            0x75 => Instruction::SaveFlags { x },
+            0x85 => Instruction::LoadFlags { x },
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some(instruction)
+}
+
+impl Instruction {
+    /// The address this instruction jumps, calls or points `I` to, if any.
+    pub fn target(&self) -> Option<u16> {
+        match *self {
+            Instruction::Jump { address }
+            | Instruction::Call { address }
+            | Instruction::LoadIndex { address }
+            | Instruction::LoadLongIndex { address }
+            | Instruction::JumpOffset { address, .. } => Some(address),
+            _ => None,
+        }
+    }
+
+    /// Formats the instruction with `target` printed as a label name instead
+    /// of a number.
+    pub fn format_with_label(&self, label: &str) -> String {
+        match *self {
+            Instruction::Jump { .. } => format!("JP {}", label),
+            Instruction::Call { .. } => format!("CALL {}", label),
+            Instruction::LoadIndex { .. } => format!("LD I, {}", label),
+            Instruction::LoadLongIndex { .. } => format!("LD I, LONG {}", label),
+            Instruction::JumpOffset { .. } => format!("JP V0, {}", label),
+            _ => self.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Instruction::ClearScreen => write!(f, "CLS"),
+            Instruction::Return => write!(f, "RET"),
+            Instruction::ScrollDown { n } => write!(f, "SCD {}", n),
+            Instruction::ScrollRight => write!(f, "SCR"),
+            Instruction::ScrollLeft => write!(f, "SCL"),
+            Instruction::Exit => write!(f, "EXIT"),
+            Instruction::LowRes => write!(f, "LOW"),
+            Instruction::HighRes => write!(f, "HIGH"),
+            Instruction::Jump { address } => write!(f, "JP {:#05x}", address),
+            Instruction::Call { address } => write!(f, "CALL {:#05x}", address),
+            Instruction::SkipEqualImmediate { x, value } => {
+                write!(f, "SE V{:X}, {:#04x}", x, value)
+            }
+            Instruction::SkipNotEqualImmediate { x, value } => {
+                write!(f, "SNE V{:X}, {:#04x}", x, value)
+            }
+            Instruction::SkipEqual { x, y } => write!(f, "SE V{:X}, V{:X}", x, y),
+            Instruction::SaveRange { x, y } => write!(f, "SAVE V{:X}-V{:X}", x, y),
+            Instruction::LoadRange { x, y } => write!(f, "LOAD V{:X}-V{:X}", x, y),
+            Instruction::LoadImmediate { x, value } => write!(f, "LD V{:X}, {:#04x}", x, value),
+            Instruction::AddImmediate { x, value } => write!(f, "ADD V{:X}, {:#04x}", x, value),
+            Instruction::Move { x, y } => write!(f, "LD V{:X}, V{:X}", x, y),
+            Instruction::Or { x, y } => write!(f, "OR V{:X}, V{:X}", x, y),
+            Instruction::And { x, y } => write!(f, "AND V{:X}, V{:X}", x, y),
+            Instruction::Xor { x, y } => write!(f, "XOR V{:X}, V{:X}", x, y),
+            Instruction::AddReg { x, y } => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Instruction::Sub { x, y } => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Instruction::ShiftRight { x, y } => write!(f, "SHR V{:X}, V{:X}", x, y),
+            Instruction::SubReverse { x, y } => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Instruction::ShiftLeft { x, y } => write!(f, "SHL V{:X}, V{:X}", x, y),
+            Instruction::SkipNotEqual { x, y } => write!(f, "SNE V{:X}, V{:X}", x, y),
+            Instruction::LoadIndex { address } => write!(f, "LD I, {:#05x}", address),
+            Instruction::JumpOffset { address, .. } => write!(f, "JP V0, {:#05x}", address),
+            Instruction::Random { x, mask } => write!(f, "RND V{:X}, {:#04x}", x, mask),
+            Instruction::Draw { x, y, n } => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            Instruction::SkipKeyPressed { x } => write!(f, "SKP V{:X}", x),
+            Instruction::SkipKeyNotPressed { x } => write!(f, "SKNP V{:X}", x),
+            Instruction::LoadLongIndex { address } => write!(f, "LD I, LONG {:#06x}", address),
+            Instruction::SelectPlanes { planes } => write!(f, "PLANE {}", planes),
+            Instruction::LoadAudioPattern => write!(f, "AUDIO"),
+            Instruction::GetDelay { x } => write!(f, "LD V{:X}, DT", x),
+            Instruction::WaitKey { x } => write!(f, "LD V{:X}, K", x),
+            Instruction::SetDelay { x } => write!(f, "LD DT, V{:X}", x),
+            Instruction::SetSound { x } => write!(f, "LD ST, V{:X}", x),
+            Instruction::AddIndex { x } => write!(f, "ADD I, V{:X}", x),
+            Instruction::Font { x } => write!(f, "LD F, V{:X}", x),
+            Instruction::BigFont { x } => write!(f, "LD HF, V{:X}", x),
+            Instruction::Bcd { x } => write!(f, "LD B, V{:X}", x),
+            Instruction::Pitch { x } => write!(f, "PITCH V{:X}", x),
+            Instruction::Store { x } => write!(f, "LD [I], V{:X}", x),
+            Instruction::Load { x } => write!(f, "LD V{:X}, [I]", x),
+            Instruction::SaveFlags { x } => write!(f, "LD R, V{:X}", x),
+            Instruction::LoadFlags { x } => write!(f, "LD V{:X}, R", x),
+        }
+    }
+}
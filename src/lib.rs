@@ -1,25 +1,41 @@
-use rand::Rng;
-use sdl2::event::Event;
-use sdl2::pixels::Color;
-use sdl2::rect::Rect;
-use sdl2::render::Canvas;
-use sdl2::EventPump;
-use std::env;
-use std::fs::File;
-use std::io::Read;
-use std::process::exit;
-
-const WIDTH: usize = 640;
-const HEIGHT: usize = 320;
-const SCALE: usize = 10;
-
-#[derive(Copy, Clone, Debug)]
-pub struct Pixel {
-    rect: Rect,
-    is_active: bool,
-}
+use audio::AudioPattern;
+use instruction::{decode, Instruction};
+use std::ops::Range;
+
+pub use error::{AsmError, EmulatorError, ErrorPolicy, StateError};
+pub use quirks::{Quirks, Variant};
+pub use rng::{Rng, RngMode};
+pub use savestate::{load_state, save_state};
+pub use screen::Screen;
+
+pub mod asm;
+pub mod audio;
+pub mod capture;
+pub mod debugger;
+pub mod disasm;
+mod error;
+pub mod frontend;
+pub mod instruction;
+pub mod movie;
+pub mod octo;
+mod quirks;
+pub mod rewind;
+mod rng;
+pub mod romdb;
+pub mod savestate;
+pub mod screen;
+#[cfg(feature = "sdl")]
+pub mod sdl;
 
-#[derive(Debug)]
+pub const FRAME_RATE: u32 = 60;
+pub const DEFAULT_INSTRUCTIONS_PER_FRAME: u32 = 8;
+/// Where programs are loaded and start executing.
+pub const PROGRAM_START: u16 = 0x200;
+const FONT_ADDRESS: u16 = 0x50;
+const BIG_FONT_ADDRESS: u16 = 0xA0;
+const STACK_SIZE: usize = 16;
+
+#[derive(Clone, Debug)]
 pub struct Chip8Cpu {
     programm_counter: u16,
     i: u16,
@@ -29,40 +45,54 @@ pub struct Chip8Cpu {
     gen_purpose_registers: [u8; 16],
 }
 
+/// The emulated machine without any window or input handling attached to it.
+/// Frontends read `screen` after `emulate` reports a redraw and write the
+/// currently held keys into `input`.
 pub struct Interpreter {
     cpu: Chip8Cpu,
-    canvas: Canvas<sdl2::video::Window>,
-    screen: [[Pixel; HEIGHT / SCALE]; WIDTH / SCALE],
-    event_pump: EventPump,
-    ram: [u8; 4096],
-    input: [bool; 16],
+    pub screen: Screen,
+    ram: Vec<u8>,
+    pub input: [bool; 16],
+    pub error_policy: ErrorPolicy,
+    /// Faults `ErrorPolicy::Skip` went past, for the frontend to show and
+    /// clear.
+    pub skipped_errors: Vec<EmulatorError>,
+    pub variant: Variant,
+    pub quirks: Quirks,
+    vblank: bool,
+    rpl_flags: [u8; 16],
+    exited: bool,
+    audio_pattern: [u8; 16],
+    pitch: u8,
+    /// Decoded instructions by address, see `decode_at`.
+    decoded: Vec<Option<Instruction>>,
+    rng: Rng,
 }
 
-impl Pixel {
-    fn build() -> Pixel {
-        Pixel {
-            rect: Rect::new(0, 0, 0, 0),
-            is_active: false,
-        }
-    }
-}
-
-pub fn setup_emulator() -> Interpreter {
-    let (canv, scr, event_p) = setup_screen();
+pub fn setup_emulator(variant: Variant, quirks: Quirks) -> Interpreter {
     let mut interpreter = Interpreter {
         cpu: Chip8Cpu {
-            programm_counter: 0x200,
+            programm_counter: PROGRAM_START,
             i: 0,
             stack: Vec::new(),
             delay_timer: 0,
             sound_timer: 0,
             gen_purpose_registers: [0; 16],
         },
-        canvas: canv,
-        screen: scr,
-        event_pump: event_p,
-        ram: [0; 4096],
+        screen: Screen::new(),
+        ram: vec![0; variant.memory_size()],
+        decoded: vec![None; variant.memory_size()],
         input: [false; 16],
+        error_policy: ErrorPolicy::default(),
+        skipped_errors: Vec::new(),
+        variant,
+        quirks,
+        vblank: false,
+        rpl_flags: [0; 16],
+        exited: false,
+        audio_pattern: [0; 16],
+        pitch: 64,
+        rng: Rng::from_time(RngMode::default()),
     };
     let font = [
         0xF0, 0x90, 0x90, 0x90, 0xF0, 0x20, 0x60, 0x20, 0x20, 0x70, 0xF0, 0x10, 0xF0, 0x80, 0xF0,
@@ -72,65 +102,71 @@ pub fn setup_emulator() -> Interpreter {
         0xF0, 0x80, 0x80, 0x80, 0xF0, 0xE0, 0x90, 0x90, 0x90, 0xE0, 0xF0, 0x80, 0xF0, 0x80, 0xF0,
         0xF0, 0x80, 0xF0, 0x80, 0x80,
     ];
-    interpreter.ram[0x50..=0x9F].copy_from_slice(&font);
+    let big_font = [
+        0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, 0x18, 0x38, 0x58, 0x18, 0x18,
+        0x18, 0x18, 0x18, 0x18, 0x3C, 0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF,
+        0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, 0x06, 0x0E, 0x1E, 0x36, 0x66,
+        0xC6, 0xFF, 0xFF, 0x06, 0x06, 0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C,
+        0x3E, 0x7C, 0xE0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, 0xFF, 0xFF, 0x03, 0x06, 0x0C,
+        0x18, 0x30, 0x60, 0x60, 0x60, 0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C,
+        0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, 0x18, 0x3C, 0x66, 0xC3, 0xC3,
+        0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xFC, 0xFE, 0xC3, 0xC3, 0xFE, 0xFE, 0xC3, 0xC3, 0xFE, 0xFC,
+        0x3C, 0x7E, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0x7E, 0x3C, 0xFC, 0xFE, 0xC3, 0xC3, 0xC3,
+        0xC3, 0xC3, 0xC3, 0xFE, 0xFC, 0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFC, 0xC0, 0xC0, 0xFF, 0xFF,
+        0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFC, 0xC0, 0xC0, 0xC0, 0xC0,
+    ];
+    interpreter.ram[FONT_ADDRESS as usize..][..font.len()].copy_from_slice(&font);
+    interpreter.ram[BIG_FONT_ADDRESS as usize..][..big_font.len()].copy_from_slice(&big_font);
     interpreter
 }
 
-fn setup_screen() -> (
-    Canvas<sdl2::video::Window>,
-    [[Pixel; HEIGHT / SCALE]; WIDTH / SCALE],
-    EventPump,
-) {
-    let mut grid: [[Pixel; HEIGHT / SCALE]; WIDTH / SCALE] =
-        [[Pixel::build(); HEIGHT / SCALE]; WIDTH / SCALE];
-    for x in 0..grid.len() {
-        for y in 0..grid[x].len() {
-            grid[x][y].rect.set_x((x * SCALE).try_into().unwrap());
-            grid[x][y].rect.set_y((y * SCALE).try_into().unwrap());
-            grid[x][y].rect.set_width(SCALE.try_into().unwrap());
-            grid[x][y].rect.set_height(SCALE.try_into().unwrap());
-        }
-    }
-    let sdl_context = sdl2::init().unwrap();
-    let video_subsystem = sdl_context.video().unwrap();
-
-    let window = video_subsystem
-        .window(
-            "chip-8 emulator",
-            WIDTH.try_into().unwrap(),
-            HEIGHT.try_into().unwrap(),
-        )
-        .position_centered()
-        .build()
-        .expect("window creation failed");
-
-    let canvas = window.into_canvas().build().expect("Canvas Builder failed");
-
-    let event_pump = sdl_context.event_pump().unwrap();
-    (canvas, grid, event_pump)
+/// Copies a program to `PROGRAM_START`, cutting off what doesn't fit into RAM.
+pub fn load_rom(interpreter: &mut Interpreter, rom: &[u8]) {
+    let start = PROGRAM_START as usize;
+    let size = rom.len().min(interpreter.ram.len() - start);
+    write_ram(interpreter, start, &rom[0..size]);
 }
 
-pub fn load_program(interpreter: &mut Interpreter) {
-    let args: Vec<String> = env::args().collect();
-    let mut file = File::open(args[1].clone()).expect("Program not found");
-    let mut buffer: [u8; 4096] = [0; 4096];
-    let size = file.read(&mut buffer).expect("Read Error");
-    interpreter.ram[0x200..(0x200 + size)].copy_from_slice(&buffer[0..size]);
+/// Starts over with `rom` as if it was just loaded. Registers, timers, the
+/// screen and RAM are reset, while the RPL flags, the random number generator
+/// and the held keys carry over.
+pub fn reset(interpreter: &mut Interpreter, rom: &[u8]) {
+    let fresh = setup_emulator(interpreter.variant, interpreter.quirks);
+    let old = std::mem::replace(interpreter, fresh);
+    interpreter.error_policy = old.error_policy;
+    interpreter.rpl_flags = old.rpl_flags;
+    interpreter.rng = old.rng;
+    interpreter.input = old.input;
+    load_rom(interpreter, rom);
 }
 
-pub fn render(interpreter: &mut Interpreter) {
-    interpreter.canvas.set_draw_color(Color::RGB(0, 0, 0));
-    interpreter.canvas.clear();
-    interpreter.canvas.set_draw_color(Color::RGB(255, 255, 255));
+/// Replaces where `CXNN` gets its random numbers from, e.g. with a seeded
+/// `Rng` to make runs reproducible.
+pub fn set_rng(interpreter: &mut Interpreter, rng: Rng) {
+    interpreter.rng = rng;
+}
 
-    for row in interpreter.screen {
-        for pixel in row {
-            if pixel.is_active {
-                let _ = interpreter.canvas.fill_rect(pixel.rect);
-            }
-        }
+/// Whether the program ran the SUPER-CHIP exit instruction `00FD`.
+pub fn has_exited(interpreter: &Interpreter) -> bool {
+    interpreter.exited
+}
+
+pub fn is_beeping(interpreter: &Interpreter) -> bool {
+    interpreter.cpu.sound_timer > 0
+}
+
+/// The XO-CHIP audio pattern loaded by `F002` and the rate in Hz its 128 bits
+/// are played back at, as set by `FX3A`. `None` plays the plain buzzer: on
+/// other variants and while the pattern is all zeros, as it is before a
+/// program loads one.
+pub fn audio_pattern(interpreter: &Interpreter) -> Option<AudioPattern> {
+    if !interpreter.variant.has_xochip_instructions()
+        || interpreter.audio_pattern.iter().all(|&byte| byte == 0)
+    {
+        return None;
     }
-    interpreter.canvas.present();
+    let rate = 4000.0 * 2f32.powf((interpreter.pitch as f32 - 64.0) / 48.0);
+    Some((interpreter.audio_pattern, rate))
 }
 
 fn get_pressed_key(interpreter: &mut Interpreter) -> Option<u8> {
@@ -142,366 +178,556 @@ fn get_pressed_key(interpreter: &mut Interpreter) -> Option<u8> {
     None
 }
 
-pub fn handle_input(interpreter: &mut Interpreter) {
-    for event in interpreter.event_pump.poll_iter() {
-        match event {
-            Event::Quit { .. } => exit(0),
-            Event::KeyDown { scancode, .. } => match scancode.unwrap() {
-                sdl2::keyboard::Scancode::Num1 => interpreter.input[1] = true,
-                sdl2::keyboard::Scancode::Num2 => interpreter.input[2] = true,
-                sdl2::keyboard::Scancode::Num3 => interpreter.input[3] = true,
-                sdl2::keyboard::Scancode::Num4 => interpreter.input[0xC] = true,
-                sdl2::keyboard::Scancode::Q => interpreter.input[4] = true,
-                sdl2::keyboard::Scancode::W => interpreter.input[5] = true,
-                sdl2::keyboard::Scancode::E => interpreter.input[6] = true,
-                sdl2::keyboard::Scancode::R => interpreter.input[0xD] = true,
-                sdl2::keyboard::Scancode::A => interpreter.input[7] = true,
-                sdl2::keyboard::Scancode::S => interpreter.input[8] = true,
-                sdl2::keyboard::Scancode::D => interpreter.input[9] = true,
-                sdl2::keyboard::Scancode::F => interpreter.input[0xE] = true,
-                sdl2::keyboard::Scancode::Z => interpreter.input[0xA] = true,
-                sdl2::keyboard::Scancode::X => interpreter.input[0] = true,
-                sdl2::keyboard::Scancode::C => interpreter.input[0xB] = true,
-                sdl2::keyboard::Scancode::V => interpreter.input[0xF] = true,
-                sdl2::keyboard::Scancode::Escape => exit(0),
-                _ => (),
-            },
-            Event::KeyUp { scancode, .. } => match scancode.unwrap() {
-                sdl2::keyboard::Scancode::Num1 => interpreter.input[1] = false,
-                sdl2::keyboard::Scancode::Num2 => interpreter.input[2] = false,
-                sdl2::keyboard::Scancode::Num3 => interpreter.input[3] = false,
-                sdl2::keyboard::Scancode::Num4 => interpreter.input[0xC] = false,
-                sdl2::keyboard::Scancode::Q => interpreter.input[4] = false,
-                sdl2::keyboard::Scancode::W => interpreter.input[5] = false,
-                sdl2::keyboard::Scancode::E => interpreter.input[6] = false,
-                sdl2::keyboard::Scancode::R => interpreter.input[0xD] = false,
-                sdl2::keyboard::Scancode::A => interpreter.input[7] = false,
-                sdl2::keyboard::Scancode::S => interpreter.input[8] = false,
-                sdl2::keyboard::Scancode::D => interpreter.input[9] = false,
-                sdl2::keyboard::Scancode::F => interpreter.input[0xE] = false,
-                sdl2::keyboard::Scancode::Z => interpreter.input[0xA] = false,
-                sdl2::keyboard::Scancode::X => interpreter.input[0] = false,
-                sdl2::keyboard::Scancode::C => interpreter.input[0xB] = false,
-                sdl2::keyboard::Scancode::V => interpreter.input[0xF] = false,
-                _ => (),
-            },
-            _ => (),
-        }
+fn fetch_word(interpreter: &Interpreter, address: u16) -> Result<u16, EmulatorError> {
+    if address as usize + 1 >= interpreter.ram.len() {
+        return Err(EmulatorError::ProgramCounterOutOfBounds { address });
     }
+    let left_part: u8 = interpreter.ram[address as usize];
+    let right_part: u8 = interpreter.ram[(address + 1) as usize];
+    Ok(((left_part as u16) << 8) + right_part as u16)
 }
 
-fn fetch_instruction(interpreter: &mut Interpreter) -> u16 {
-    let left_part: u8 = interpreter.ram[interpreter.cpu.programm_counter as usize] as u8;
-    let right_part: u8 = interpreter.ram[(interpreter.cpu.programm_counter + 1) as usize] as u8;
-    ((left_part as u16) << 8) + right_part as u16
+/// Decodes the instruction at `address`, from the cache if it was decoded
+/// before. `None` is an opcode no variant knows, those are not cached.
+fn decode_at(
+    interpreter: &mut Interpreter,
+    address: u16,
+) -> Result<Option<Instruction>, EmulatorError> {
+    let opcode = fetch_word(interpreter, address)?;
+    if let Some(instruction) = interpreter.decoded[address as usize] {
+        return Ok(Some(instruction));
+    }
+    let next = if opcode == 0xF000 && interpreter.variant.has_xochip_instructions() {
+        fetch_word(interpreter, address.wrapping_add(2))?
+    } else {
+        0
+    };
+    let instruction = decode(opcode, next);
+    interpreter.decoded[address as usize] = instruction;
+    Ok(instruction)
 }
 
-fn clear_screen(interpreter: &mut Interpreter) {
-    for i in 0..interpreter.screen.len() {
-        for j in 0..interpreter.screen[i].len() {
-            interpreter.screen[i][j].is_active = false;
-        }
+/// Writes `bytes` to RAM at `start` and forgets the decoded instructions
+/// overlapping them, so self-modifying code runs what it wrote.
+fn write_ram(interpreter: &mut Interpreter, start: usize, bytes: &[u8]) {
+    interpreter.ram[start..start + bytes.len()].copy_from_slice(bytes);
+    // An instruction is at most four bytes long, one starting up to three
+    // bytes before the write can contain it.
+    let first = start.saturating_sub(3);
+    interpreter.decoded[first..start + bytes.len()].fill(None);
+}
+
+/// Returns the RAM indices `I..I + len`, or an error if they leave memory.
+fn ram_range_at_i(
+    interpreter: &Interpreter,
+    len: usize,
+    address: u16,
+) -> Result<Range<usize>, EmulatorError> {
+    let start = interpreter.cpu.i as usize;
+    if start + len > interpreter.ram.len() {
+        return Err(EmulatorError::MemoryOutOfBounds {
+            index: start + len - 1,
+            address,
+        });
     }
+    Ok(start..start + len)
 }
 
-pub fn emulate(interpreter: &mut Interpreter, cycle_num: u64) -> bool {
-    let instruction: u16 = fetch_instruction(interpreter);
-    /*println!("Instruction: {:#06x}\n{:#?}", instruction, interpreter.cpu);
-    let mut guess = String::new();
-    std::io::stdin()
-        .read_line(&mut guess)
-        .expect("Failed to read line");*/
-    interpreter.cpu.programm_counter += 2;
-    if cycle_num % 8 == 0 {
-        if interpreter.cpu.delay_timer > 0 {
-            interpreter.cpu.delay_timer -= 1;
-        }
-        if interpreter.cpu.sound_timer > 0 {
-            interpreter.cpu.sound_timer -= 1;
-        } else {
-            // println!("beep");
+/// Skips the next instruction, which on XO-CHIP can be the four byte `F000 NNNN`.
+fn skip_instruction(interpreter: &mut Interpreter, address: u16) -> Result<(), EmulatorError> {
+    let next = interpreter.cpu.programm_counter as usize;
+    if interpreter.variant.has_xochip_instructions()
+        && next + 1 < interpreter.ram.len()
+        && interpreter.ram[next] == 0xF0
+        && interpreter.ram[next + 1] == 0x00
+    {
+        advance(interpreter, 4, address)
+    } else {
+        advance(interpreter, 2, address)
+    }
+}
+
+/// Moves the program counter `bytes` on. Past the end of 64 KiB there is no
+/// address left, which is reported for the instruction at `address`.
+fn advance(interpreter: &mut Interpreter, bytes: u16, address: u16) -> Result<(), EmulatorError> {
+    interpreter.cpu.programm_counter = interpreter
+        .cpu
+        .programm_counter
+        .checked_add(bytes)
+        .ok_or(EmulatorError::ProgramCounterOutOfBounds { address })?;
+    Ok(())
+}
+
+/// The registers VX to VY of `5XY2`/`5XY3`, in descending order if X > Y.
+fn register_range(x: u8, y: u8) -> Vec<usize> {
+    let (x, y) = (x as usize, y as usize);
+    if x <= y {
+        (x..=y).collect()
+    } else {
+        (y..=x).rev().collect()
+    }
+}
+
+/// The register `8XY6`/`8XYE` shift, which is VY unless the shift quirk is set.
+fn shift_source(interpreter: &Interpreter, x: u8, y: u8) -> usize {
+    if interpreter.quirks.shift {
+        x as usize
+    } else {
+        y as usize
+    }
+}
+
+/// Counts the delay and sound timer down, which happens at 60 Hz.
+pub fn tick_timers(interpreter: &mut Interpreter) {
+    interpreter.vblank = true;
+    if interpreter.cpu.delay_timer > 0 {
+        interpreter.cpu.delay_timer -= 1;
+    }
+    if interpreter.cpu.sound_timer > 0 {
+        interpreter.cpu.sound_timer -= 1;
+    }
+}
+
+/// Emulates one 60 Hz frame: runs `instructions_per_frame` instructions and
+/// then ticks the timers once, so their rate only depends on emulated time.
+/// Returns whether the screen changed during the frame.
+pub fn run_frame(
+    interpreter: &mut Interpreter,
+    instructions_per_frame: u32,
+) -> Result<bool, EmulatorError> {
+    let mut redraw = false;
+    for _ in 0..instructions_per_frame {
+        if interpreter.exited {
+            break;
         }
+        redraw |= emulate(interpreter)?;
     }
-    match instruction >> 12 {
-        0 => match (instruction & 0x0F00) >> 8 {
-            0x0 => match instruction & 0x00FF {
-                0xE0 => {
-                    clear_screen(interpreter);
-                    return true;
-                }
-                0xEE => {
-                    interpreter.cpu.programm_counter =
-                        interpreter.cpu.stack.pop().expect("Empty Stack popped")
-                }
-                _ => eprintln!("Invalid Instruction: {:#06x}", instruction),
-            },
-            _ => eprintln!("Invalid Instruction: {:#06x}", instruction),
-        },
-        1 => interpreter.cpu.programm_counter = instruction & 0x0FFF,
-        2 => {
-            interpreter.cpu.stack.push(interpreter.cpu.programm_counter);
-            interpreter.cpu.programm_counter = instruction & 0x0FFF;
+    tick_timers(interpreter);
+    Ok(redraw)
+}
+
+/// Runs one instruction. Returns whether the screen changed, or the fault the
+/// instruction ran into if `error_policy` is `ErrorPolicy::Halt`. A program
+/// counter outside of RAM is always reported since there is nothing to skip.
+pub fn emulate(interpreter: &mut Interpreter) -> Result<bool, EmulatorError> {
+    let address = interpreter.cpu.programm_counter;
+    let instruction = decode_at(interpreter, address)?;
+    advance(interpreter, 2, address)?;
+    let result = match instruction {
+        Some(instruction) if instruction.is_supported_by(interpreter.variant) => {
+            execute(interpreter, instruction, address)
         }
-        3 => {
-            if interpreter.cpu.gen_purpose_registers[((instruction & 0x0F00) >> 8) as usize]
-                == (instruction & 0x00FF) as u8
-            {
-                interpreter.cpu.programm_counter += 2;
+        _ => Err(EmulatorError::UnknownOpcode {
+            opcode: fetch_word(interpreter, address)?,
+            address,
+        }),
+    };
+    match result {
+        Ok(redraw) => Ok(redraw),
+        Err(err) => match interpreter.error_policy {
+            ErrorPolicy::Halt => {
+                interpreter.cpu.programm_counter = address;
+                Err(err)
             }
-        }
-        4 => {
-            if interpreter.cpu.gen_purpose_registers[((instruction & 0x0F00) >> 8) as usize]
-                != (instruction & 0x00FF) as u8
-            {
-                interpreter.cpu.programm_counter += 2;
+            ErrorPolicy::Skip => {
+                let size = instruction.map_or(2, |instruction| instruction.size());
+                interpreter.cpu.programm_counter = address;
+                advance(interpreter, size, address)?;
+                interpreter.skipped_errors.push(err);
+                Ok(false)
             }
-        }
-        5 => {
-            if interpreter.cpu.gen_purpose_registers[((instruction & 0x0F00) >> 8) as usize]
-                == interpreter.cpu.gen_purpose_registers[((instruction & 0x00F0) >> 4) as usize]
-            {
-                interpreter.cpu.programm_counter += 2;
+            ErrorPolicy::Nop => {
+                interpreter.cpu.programm_counter = address;
+                advance(interpreter, 2, address)?;
+                Ok(false)
             }
+        },
+    }
+}
+
+fn execute(
+    interpreter: &mut Interpreter,
+    instruction: Instruction,
+    address: u16,
+) -> Result<bool, EmulatorError> {
+    match instruction {
+        Instruction::ClearScreen => {
+            interpreter.screen.clear();
+            return Ok(true);
         }
-        6 => {
-            interpreter.cpu.gen_purpose_registers[((instruction & 0x0F00) >> 8) as usize] =
-                (instruction & 0x00FF) as u8;
+        Instruction::Return => {
+            interpreter.cpu.programm_counter = interpreter
+                .cpu
+                .stack
+                .pop()
+                .ok_or(EmulatorError::StackUnderflow { address })?
         }
-        7 => {
-            interpreter.cpu.gen_purpose_registers[((instruction & 0xF00) >> 8) as usize] =
-                interpreter.cpu.gen_purpose_registers[((instruction & 0xF00) >> 8) as usize]
-                    .wrapping_add((instruction & 0x00FF) as u8);
+        Instruction::ScrollDown { n } => {
+            interpreter.screen.scroll_down(n as usize);
+            return Ok(true);
         }
-        8 => match instruction & 0x000F {
-            0 => {
-                interpreter.cpu.gen_purpose_registers[((instruction & 0x0F00) >> 8) as usize] =
-                    interpreter.cpu.gen_purpose_registers[((instruction & 0x00F0) >> 4) as usize];
-            }
-            1 => {
-                interpreter.cpu.gen_purpose_registers[((instruction & 0x0F00) >> 8) as usize] =
-                    interpreter.cpu.gen_purpose_registers[((instruction & 0x0F00) >> 8) as usize]
-                        | interpreter.cpu.gen_purpose_registers
-                            [((instruction & 0x00F0) >> 4) as usize];
-            }
-            2 => {
-                interpreter.cpu.gen_purpose_registers[((instruction & 0x0F00) >> 8) as usize] =
-                    interpreter.cpu.gen_purpose_registers[((instruction & 0x0F00) >> 8) as usize]
-                        & interpreter.cpu.gen_purpose_registers
-                            [((instruction & 0x00F0) >> 4) as usize];
+        Instruction::ScrollRight => {
+            interpreter.screen.scroll_right(4);
+            return Ok(true);
+        }
+        Instruction::ScrollLeft => {
+            interpreter.screen.scroll_left(4);
+            return Ok(true);
+        }
+        Instruction::Exit => {
+            interpreter.exited = true;
+            interpreter.cpu.programm_counter = address;
+        }
+        Instruction::LowRes => {
+            interpreter.screen.set_hires(false);
+            return Ok(true);
+        }
+        Instruction::HighRes => {
+            interpreter.screen.set_hires(true);
+            return Ok(true);
+        }
+        Instruction::Jump { address } => interpreter.cpu.programm_counter = address,
+        Instruction::Call { address: target } => {
+            if interpreter.cpu.stack.len() >= STACK_SIZE {
+                return Err(EmulatorError::StackOverflow { address });
             }
-            3 => {
-                interpreter.cpu.gen_purpose_registers[((instruction & 0x0F00) >> 8) as usize] =
-                    interpreter.cpu.gen_purpose_registers[((instruction & 0x0F00) >> 8) as usize]
-                        ^ interpreter.cpu.gen_purpose_registers
-                            [((instruction & 0x00F0) >> 4) as usize];
+            interpreter.cpu.stack.push(interpreter.cpu.programm_counter);
+            interpreter.cpu.programm_counter = target;
+        }
+        Instruction::SkipEqualImmediate { x, value } => {
+            if interpreter.cpu.gen_purpose_registers[x as usize] == value {
+                skip_instruction(interpreter, address)?;
             }
-            4 => {
-                let result = interpreter.cpu.gen_purpose_registers
-                    [((instruction & 0x0F00) >> 8) as usize] as u16
-                    + interpreter.cpu.gen_purpose_registers[((instruction & 0x00F0) >> 4) as usize]
-                        as u16;
-                interpreter.cpu.gen_purpose_registers[((instruction & 0x0F00) >> 8) as usize] =
-                    (result & 0xFF) as u8;
-                if result > 0xFF {
-                    interpreter.cpu.gen_purpose_registers[15] = 1;
-                } else {
-                    interpreter.cpu.gen_purpose_registers[15] = 0;
-                }
+        }
+        Instruction::SkipNotEqualImmediate { x, value } => {
+            if interpreter.cpu.gen_purpose_registers[x as usize] != value {
+                skip_instruction(interpreter, address)?;
             }
-            5 => {
-                if interpreter.cpu.gen_purpose_registers[((instruction & 0x0F00) >> 8) as usize]
-                    >= interpreter.cpu.gen_purpose_registers[((instruction & 0x00F0) >> 4) as usize]
-                {
-                    interpreter.cpu.gen_purpose_registers[((instruction & 0x0F00) >> 8) as usize] =
-                        interpreter.cpu.gen_purpose_registers
-                            [((instruction & 0x0F00) >> 8) as usize]
-                            - interpreter.cpu.gen_purpose_registers
-                                [((instruction & 0x00F0) >> 4) as usize];
-                    interpreter.cpu.gen_purpose_registers[15] = 1;
-                } else {
-                    interpreter.cpu.gen_purpose_registers[((instruction & 0x0F00) >> 8) as usize] =
-                        (0x100
-                            - interpreter.cpu.gen_purpose_registers
-                                [((instruction & 0x00F0) >> 4) as usize]
-                                as u16
-                            + interpreter.cpu.gen_purpose_registers
-                                [((instruction & 0x0F00) >> 8) as usize]
-                                as u16) as u8;
-                    interpreter.cpu.gen_purpose_registers[15] = 0x00;
-                }
+        }
+        Instruction::SkipEqual { x, y } => {
+            let v = &interpreter.cpu.gen_purpose_registers;
+            if v[x as usize] == v[y as usize] {
+                skip_instruction(interpreter, address)?;
             }
-            6 => {
-                let val =
-                    interpreter.cpu.gen_purpose_registers[((instruction & 0x00F0) >> 4) as usize];
-                interpreter.cpu.gen_purpose_registers[((instruction & 0x00F0) >> 4) as usize] =
-                    interpreter.cpu.gen_purpose_registers[((instruction & 0x00F0) >> 4) as usize]
-                        >> 1;
-                interpreter.cpu.gen_purpose_registers[((instruction & 0x0F00) >> 8) as usize] =
-                    interpreter.cpu.gen_purpose_registers[((instruction & 0x00F0) >> 4) as usize];
-                interpreter.cpu.gen_purpose_registers[15] = val & 0x1;
+        }
+        Instruction::SaveRange { x, y } => {
+            let values: Vec<u8> = register_range(x, y)
+                .into_iter()
+                .map(|register| interpreter.cpu.gen_purpose_registers[register])
+                .collect();
+            let target = ram_range_at_i(interpreter, values.len(), address)?;
+            write_ram(interpreter, target.start, &values);
+        }
+        Instruction::LoadRange { x, y } => {
+            let registers = register_range(x, y);
+            let source = ram_range_at_i(interpreter, registers.len(), address)?;
+            for (index, register) in source.zip(registers) {
+                interpreter.cpu.gen_purpose_registers[register] = interpreter.ram[index];
             }
-            7 => {
-                if interpreter.cpu.gen_purpose_registers[((instruction & 0x00F0) >> 4) as usize]
-                    >= interpreter.cpu.gen_purpose_registers[((instruction & 0x0F00) >> 8) as usize]
-                {
-                    interpreter.cpu.gen_purpose_registers[((instruction & 0x0F00) >> 8) as usize] =
-                        interpreter.cpu.gen_purpose_registers
-                            [((instruction & 0x00F0) >> 4) as usize]
-                            - interpreter.cpu.gen_purpose_registers
-                                [((instruction & 0x0F00) >> 8) as usize];
-                    interpreter.cpu.gen_purpose_registers[15] = 1;
-                } else {
-                    interpreter.cpu.gen_purpose_registers[((instruction & 0x0F00) >> 8) as usize] =
-                        (0x100
-                            - interpreter.cpu.gen_purpose_registers
-                                [((instruction & 0x0F00) >> 8) as usize]
-                                as u16
-                            + interpreter.cpu.gen_purpose_registers
-                                [((instruction & 0x00F0) >> 4) as usize]
-                                as u16) as u8;
-                    interpreter.cpu.gen_purpose_registers[15] = 0;
-                }
+        }
+        Instruction::LoadImmediate { x, value } => {
+            interpreter.cpu.gen_purpose_registers[x as usize] = value;
+        }
+        Instruction::AddImmediate { x, value } => {
+            let v = &mut interpreter.cpu.gen_purpose_registers;
+            v[x as usize] = v[x as usize].wrapping_add(value);
+        }
+        Instruction::Move { x, y } => {
+            let v = &mut interpreter.cpu.gen_purpose_registers;
+            v[x as usize] = v[y as usize];
+        }
+        Instruction::Or { x, y } => {
+            let v = &mut interpreter.cpu.gen_purpose_registers;
+            v[x as usize] |= v[y as usize];
+            if interpreter.quirks.vf_reset {
+                v[15] = 0;
             }
-            14 => {
-                let val =
-                    interpreter.cpu.gen_purpose_registers[((instruction & 0x00F0) >> 4) as usize];
-                interpreter.cpu.gen_purpose_registers[((instruction & 0x00F0) >> 4) as usize] =
-                    interpreter.cpu.gen_purpose_registers[((instruction & 0x00F0) >> 4) as usize]
-                        << 1;
-                interpreter.cpu.gen_purpose_registers[((instruction & 0x0F00) >> 8) as usize] =
-                    interpreter.cpu.gen_purpose_registers[((instruction & 0x00F0) >> 4) as usize];
-                interpreter.cpu.gen_purpose_registers[15] = (val >> 7) & 0x1;
+        }
+        Instruction::And { x, y } => {
+            let v = &mut interpreter.cpu.gen_purpose_registers;
+            v[x as usize] &= v[y as usize];
+            if interpreter.quirks.vf_reset {
+                v[15] = 0;
             }
-            _ => {
-                panic!("Invalid Instruction {:#06x}", instruction);
+        }
+        Instruction::Xor { x, y } => {
+            let v = &mut interpreter.cpu.gen_purpose_registers;
+            v[x as usize] ^= v[y as usize];
+            if interpreter.quirks.vf_reset {
+                v[15] = 0;
             }
-        },
-        9 => {
-            if interpreter.cpu.gen_purpose_registers[((instruction & 0x0F00) >> 8) as usize]
-                != interpreter.cpu.gen_purpose_registers[((instruction & 0x00F0) >> 4) as usize]
-            {
-                interpreter.cpu.programm_counter += 2;
+        }
+        Instruction::AddReg { x, y } => {
+            let v = &mut interpreter.cpu.gen_purpose_registers;
+            let (result, carry) = v[x as usize].overflowing_add(v[y as usize]);
+            v[x as usize] = result;
+            v[15] = carry as u8;
+        }
+        Instruction::Sub { x, y } => {
+            let v = &mut interpreter.cpu.gen_purpose_registers;
+            let (result, borrow) = v[x as usize].overflowing_sub(v[y as usize]);
+            v[x as usize] = result;
+            v[15] = !borrow as u8;
+        }
+        Instruction::ShiftRight { x, y } => {
+            let source = shift_source(interpreter, x, y);
+            let v = &mut interpreter.cpu.gen_purpose_registers;
+            let val = v[source];
+            v[x as usize] = val >> 1;
+            v[15] = val & 0x1;
+        }
+        Instruction::SubReverse { x, y } => {
+            let v = &mut interpreter.cpu.gen_purpose_registers;
+            let (result, borrow) = v[y as usize].overflowing_sub(v[x as usize]);
+            v[x as usize] = result;
+            v[15] = !borrow as u8;
+        }
+        Instruction::ShiftLeft { x, y } => {
+            let source = shift_source(interpreter, x, y);
+            let v = &mut interpreter.cpu.gen_purpose_registers;
+            let val = v[source];
+            v[x as usize] = val << 1;
+            v[15] = (val >> 7) & 0x1;
+        }
+        Instruction::SkipNotEqual { x, y } => {
+            let v = &interpreter.cpu.gen_purpose_registers;
+            if v[x as usize] != v[y as usize] {
+                skip_instruction(interpreter, address)?;
             }
         }
-        10 => interpreter.cpu.i = instruction & 0x0FFF,
-        11 => {
+        Instruction::LoadIndex { address } => interpreter.cpu.i = address,
+        Instruction::JumpOffset { x, address } => {
+            let offset_register = if interpreter.quirks.jump_with_vx {
+                x as usize
+            } else {
+                0
+            };
             interpreter.cpu.programm_counter =
-                (instruction & 0x0FFF) + interpreter.cpu.gen_purpose_registers[0] as u16;
+                address + interpreter.cpu.gen_purpose_registers[offset_register] as u16;
         }
-        12 => {
-            interpreter.cpu.gen_purpose_registers[((instruction & 0x0F00) >> 8) as usize] =
-                rand::thread_rng().gen_range(0..=0xFF) as u8 & (instruction & 0xFF) as u8;
+        Instruction::Random { x, mask } => {
+            interpreter.cpu.gen_purpose_registers[x as usize] = interpreter.rng.next_byte() & mask;
         }
-        13 => {
-            interpreter.cpu.gen_purpose_registers[15] = 0;
-            let x =
-                interpreter.cpu.gen_purpose_registers[((instruction & 0x0F00) >> 8) as usize] % 64;
-            let y =
-                interpreter.cpu.gen_purpose_registers[((instruction & 0x00F0) >> 4) as usize] % 32;
-            let n = (instruction & 0x000F) as u8;
-            interpreter.cpu.gen_purpose_registers[15] = 0;
-            for i in 0..n as usize {
-                if y as usize + i > 31 {
-                    break;
+        Instruction::Draw { x, y, n } => {
+            if interpreter.quirks.display_wait {
+                if !interpreter.vblank {
+                    interpreter.cpu.programm_counter -= 2;
+                    return Ok(false);
                 }
-                let data = interpreter.ram[interpreter.cpu.i as usize + i as usize];
-                for j in 0..8 {
-                    if x as usize + j > 63 {
+                interpreter.vblank = false;
+            }
+            let width = interpreter.screen.width();
+            let height = interpreter.screen.height();
+            let x = interpreter.cpu.gen_purpose_registers[x as usize] as usize % width;
+            let y = interpreter.cpu.gen_purpose_registers[y as usize] as usize % height;
+            // SUPER-CHIP draws a 16x16 sprite made of two bytes per row for DXY0.
+            let (rows, bytes_per_row) = match n {
+                0 if interpreter.variant.has_superchip_instructions() => (16, 2),
+                n => (n as usize, 1),
+            };
+            // XO-CHIP stores one sprite after the other for every selected plane.
+            let planes = interpreter.screen.selected_planes();
+            let sprite_len = rows * bytes_per_row;
+            let sprite = ram_range_at_i(
+                interpreter,
+                sprite_len * planes.count_ones() as usize,
+                address,
+            )?;
+            interpreter.cpu.gen_purpose_registers[15] = 0;
+            let selected = [1, 2].into_iter().filter(|plane| planes & plane != 0);
+            for (layer, plane) in selected.enumerate() {
+                let start = sprite.start + layer * sprite_len;
+                for i in 0..rows {
+                    if y + i >= height && interpreter.quirks.clipping {
                         break;
                     }
-                    let bit = data >> 7 - j;
-                    if bit & 0x1 == 1 {
-                        if interpreter.screen[x as usize + j][y as usize + i].is_active {
-                            interpreter.screen[x as usize + j][y as usize + i].is_active = false;
+                    let row = (y + i) % height;
+                    for j in 0..bytes_per_row * 8 {
+                        if x + j >= width && interpreter.quirks.clipping {
+                            break;
+                        }
+                        let column = (x + j) % width;
+                        let data = interpreter.ram[start + i * bytes_per_row + j / 8];
+                        let bit = data >> (7 - j % 8);
+                        if bit & 0x1 == 1 && interpreter.screen.flip(column, row, plane) {
                             interpreter.cpu.gen_purpose_registers[15] = 1;
-                        } else {
-                            interpreter.screen[x as usize + j][y as usize + i].is_active = true;
                         }
                     }
                 }
             }
-            return true;
-        }
-        14 => match instruction & 0x00FF {
-            0x9E => {
-                if interpreter.input[interpreter.cpu.gen_purpose_registers
-                    [((instruction & 0x0F00) >> 8) as usize]
-                    as usize]
-                {
-                    interpreter.cpu.programm_counter += 2;
-                }
+            return Ok(true);
+        }
+        Instruction::SkipKeyPressed { x } => {
+            let key = (interpreter.cpu.gen_purpose_registers[x as usize] & 0xF) as usize;
+            if interpreter.input[key] {
+                skip_instruction(interpreter, address)?;
             }
-            0xA1 => {
-                if !(interpreter.input[interpreter.cpu.gen_purpose_registers
-                    [((instruction & 0x0F00) >> 8) as usize]
-                    as usize])
-                {
-                    interpreter.cpu.programm_counter += 2;
-                }
+        }
+        Instruction::SkipKeyNotPressed { x } => {
+            let key = (interpreter.cpu.gen_purpose_registers[x as usize] & 0xF) as usize;
+            if !interpreter.input[key] {
+                skip_instruction(interpreter, address)?;
             }
-            _ => panic!("Invalid Instruction: {:#06x}", instruction),
+        }
+        Instruction::LoadLongIndex { address: target } => {
+            advance(interpreter, 2, address)?;
+            interpreter.cpu.i = target;
+        }
+        Instruction::SelectPlanes { planes } => interpreter.screen.select_planes(planes),
+        Instruction::LoadAudioPattern => {
+            let pattern = ram_range_at_i(interpreter, 16, address)?;
+            interpreter
+                .audio_pattern
+                .copy_from_slice(&interpreter.ram[pattern]);
+        }
+        Instruction::Pitch { x } => {
+            interpreter.pitch = interpreter.cpu.gen_purpose_registers[x as usize];
+        }
+        Instruction::GetDelay { x } => {
+            interpreter.cpu.gen_purpose_registers[x as usize] = interpreter.cpu.delay_timer
+        }
+        Instruction::SetDelay { x } => {
+            interpreter.cpu.delay_timer = interpreter.cpu.gen_purpose_registers[x as usize];
+        }
+        Instruction::SetSound { x } => {
+            interpreter.cpu.sound_timer = interpreter.cpu.gen_purpose_registers[x as usize];
+        }
+        Instruction::AddIndex { x } => {
+            let result =
+                interpreter.cpu.gen_purpose_registers[x as usize] as u32 + interpreter.cpu.i as u32;
+            interpreter.cpu.gen_purpose_registers[15] = (result > 0xFFFF) as u8;
+            interpreter.cpu.i = (result as usize & (interpreter.ram.len() - 1)) as u16;
+        }
+        Instruction::WaitKey { x } => match get_pressed_key(interpreter) {
+            Some(key) => interpreter.cpu.gen_purpose_registers[x as usize] = key,
+            None => interpreter.cpu.programm_counter -= 2,
         },
-        15 => match instruction & 0xFF {
-            0x7 => {
-                interpreter.cpu.gen_purpose_registers[((instruction & 0x0F00) >> 8) as usize] =
-                    interpreter.cpu.delay_timer
-            }
-            0x15 => {
-                interpreter.cpu.delay_timer =
-                    interpreter.cpu.gen_purpose_registers[((instruction & 0x0F00) >> 8) as usize];
-            }
-            0x18 => {
-                interpreter.cpu.sound_timer =
-                    interpreter.cpu.gen_purpose_registers[((instruction & 0x0F00) >> 8) as usize];
-            }
-            0x1E => {
-                let result = interpreter.cpu.gen_purpose_registers
-                    [((instruction & 0x0F00) >> 8) as usize] as u32
-                    + interpreter.cpu.i as u32;
-                if result > 0xFFFF {
-                    interpreter.cpu.gen_purpose_registers[15] = 1;
-                } else {
-                    interpreter.cpu.gen_purpose_registers[15] = 0;
-                }
-                interpreter.cpu.i = (result & 0xFFF) as u16;
-            }
-            0x0A => match get_pressed_key(interpreter) {
-                Some(key) => {
-                    interpreter.cpu.gen_purpose_registers[((instruction & 0x0F00) >> 8) as usize] =
-                        key;
-                }
-                None => interpreter.cpu.programm_counter -= 2,
-            },
-            0x29 => {
-                interpreter.cpu.i = interpreter.cpu.gen_purpose_registers
-                    [(instruction & 0x000F) as usize] as u16
-                    * 5
-                    + 0x50;
-            }
-            0x33 => {
-                let num =
-                    interpreter.cpu.gen_purpose_registers[((instruction & 0x0F00) >> 8) as usize];
-                interpreter.ram[interpreter.cpu.i as usize] = num / 100;
-                interpreter.ram[(interpreter.cpu.i + 1) as usize] = num / 10 % 10;
-                interpreter.ram[(interpreter.cpu.i + 2) as usize] = num % 10;
-            }
-            0x55 => {
-                let limit = (instruction & 0x0F00) >> 8;
-                for i in 0..=limit {
-                    interpreter.ram[(interpreter.cpu.i + i) as usize] =
-                        interpreter.cpu.gen_purpose_registers[i as usize];
-                }
+        Instruction::Font { x } => {
+            interpreter.cpu.i =
+                (interpreter.cpu.gen_purpose_registers[x as usize] & 0xF) as u16 * 5 + FONT_ADDRESS;
+        }
+        Instruction::BigFont { x } => {
+            interpreter.cpu.i = (interpreter.cpu.gen_purpose_registers[x as usize] & 0xF) as u16
+                * 10
+                + BIG_FONT_ADDRESS;
+        }
+        Instruction::Bcd { x } => {
+            let num = interpreter.cpu.gen_purpose_registers[x as usize];
+            let digits = ram_range_at_i(interpreter, 3, address)?;
+            write_ram(
+                interpreter,
+                digits.start,
+                &[num / 100, num / 10 % 10, num % 10],
+            );
+        }
+        Instruction::Store { x } => {
+            let limit = x as usize;
+            let target = ram_range_at_i(interpreter, limit + 1, address)?;
+            let values = interpreter.cpu.gen_purpose_registers;
+            write_ram(interpreter, target.start, &values[0..=limit]);
+            if interpreter.quirks.memory_increment {
+                interpreter.cpu.i = interpreter.cpu.i.wrapping_add(limit as u16 + 1);
             }
-            0x65 => {
-                let limit = (instruction & 0x0F00) >> 8;
-                for i in 0..=limit {
-                    interpreter.cpu.gen_purpose_registers[i as usize] =
-                        interpreter.ram[(interpreter.cpu.i + i) as usize];
-                }
+        }
+        Instruction::Load { x } => {
+            let limit = x as usize;
+            let source = ram_range_at_i(interpreter, limit + 1, address)?;
+            interpreter.cpu.gen_purpose_registers[0..=limit]
+                .copy_from_slice(&interpreter.ram[source]);
+            if interpreter.quirks.memory_increment {
+                interpreter.cpu.i = interpreter.cpu.i.wrapping_add(limit as u16 + 1);
             }
-            _ => panic!("Invalid Instruction: {:#06x}", instruction),
-        },
-        _ => panic!("Invalid Instruction: {:#06x}", instruction),
+        }
+        Instruction::SaveFlags { x } => {
+            let limit = x as usize;
+            interpreter.rpl_flags[0..=limit]
+                .copy_from_slice(&interpreter.cpu.gen_purpose_registers[0..=limit]);
+        }
+        Instruction::LoadFlags { x } => {
+            let limit = x as usize;
+            interpreter.cpu.gen_purpose_registers[0..=limit]
+                .copy_from_slice(&interpreter.rpl_flags[0..=limit]);
+        }
     };
-    return false;
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn machine(variant: Variant, program: &[u8]) -> Interpreter {
+        let mut interpreter = setup_emulator(variant, Quirks::default());
+        load_rom(&mut interpreter, program);
+        interpreter
+    }
+
+    #[test]
+    fn key_skips_use_the_low_nibble_of_vx() {
+        // V0 = 0x25, skip if key 5 is pressed.
+        let mut interpreter = machine(Variant::Chip8, &[0x60, 0x25, 0xE0, 0x9E]);
+        interpreter.input[5] = true;
+        emulate(&mut interpreter).unwrap();
+        emulate(&mut interpreter).unwrap();
+        assert_eq!(interpreter.cpu.programm_counter, PROGRAM_START + 6);
+    }
+
+    #[test]
+    fn program_counter_does_not_wrap_past_64_kib() {
+        let mut interpreter = machine(Variant::XoChip, &[]);
+        interpreter.ram[0xFFFE..].copy_from_slice(&[0x00, 0xE0]);
+        interpreter.cpu.programm_counter = 0xFFFE;
+        assert_eq!(
+            emulate(&mut interpreter),
+            Err(EmulatorError::ProgramCounterOutOfBounds { address: 0xFFFE })
+        );
+    }
+
+    #[test]
+    fn memory_increment_wraps_i_at_64_kib() {
+        // FF55 with I = 0xFFF0 stores all registers up to the last byte.
+        let mut interpreter = machine(Variant::XoChip, &[0xFF, 0x55]);
+        interpreter.quirks.memory_increment = true;
+        interpreter.cpu.i = 0xFFF0;
+        emulate(&mut interpreter).unwrap();
+        assert_eq!(interpreter.cpu.i, 0);
+    }
+
+    #[test]
+    fn skipped_errors_are_collected() {
+        let mut interpreter = machine(Variant::Chip8, &[0x00, 0xFF, 0x60, 0x01]);
+        interpreter.error_policy = ErrorPolicy::Skip;
+        assert_eq!(run_frame(&mut interpreter, 2), Ok(false));
+        assert_eq!(
+            interpreter.skipped_errors,
+            [EmulatorError::UnknownOpcode {
+                opcode: 0x00FF,
+                address: PROGRAM_START
+            }]
+        );
+        assert_eq!(interpreter.cpu.gen_purpose_registers[0], 1);
+    }
+
+    #[test]
+    fn long_load_past_64_kib_faults_at_its_own_address() {
+        let mut interpreter = machine(Variant::XoChip, &[]);
+        interpreter.ram[0xFFFC..].copy_from_slice(&[0xF0, 0x00, 0x12, 0x34]);
+        interpreter.cpu.programm_counter = 0xFFFC;
+        assert_eq!(
+            emulate(&mut interpreter),
+            Err(EmulatorError::ProgramCounterOutOfBounds { address: 0xFFFC })
+        );
+        assert_eq!(interpreter.cpu.i, 0);
+    }
+
+    #[test]
+    fn skip_steps_over_the_whole_instruction_and_nop_over_a_word() {
+        // F000 NNNN is unknown to CHIP-8.
+        for (policy, next) in [(ErrorPolicy::Skip, 4), (ErrorPolicy::Nop, 2)] {
+            let mut interpreter = machine(Variant::Chip8, &[0xF0, 0x00, 0x12, 0x34]);
+            interpreter.error_policy = policy;
+            assert_eq!(emulate(&mut interpreter), Ok(false));
+            assert_eq!(interpreter.cpu.programm_counter, PROGRAM_START + next);
+        }
+    }
 }
@@ -1,28 +1,822 @@
+mod cli;
+mod config;
+
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 use std::time::Duration;
 use std::time::Instant;
 
-use chip_8_emulator::emulate;
+use chip_8_emulator::audio::{AudioPattern, AudioSettings};
+use chip_8_emulator::capture::{self, GifRecorder};
+use chip_8_emulator::debugger::Debugger;
+use chip_8_emulator::disasm::decode_rom;
+use chip_8_emulator::frontend::{
+    Audio, AudioRecorder, Display, Hotkey, Keypad, NullFrontend, Palette,
+};
+use chip_8_emulator::movie::Movie;
+use chip_8_emulator::octo;
+use chip_8_emulator::rewind::RewindBuffer;
+use chip_8_emulator::romdb::{self, Database};
+use chip_8_emulator::savestate::{load_state_from_file, save_state_to_file};
+use chip_8_emulator::{EmulatorError, Interpreter, Rng, Screen, Variant, PROGRAM_START};
+use cli::{Command, MachineArgs, RunArgs, TestArgs};
+use config::{Config, Settings};
+
+const STATE_SLOTS: u8 = 10;
+
+/// How fast frames run, changed by hotkeys.
+const SPEED_FACTOR: u32 = 4;
+
+/// How a ROM is run, once all options are applied.
+struct Session {
+    rom_path: PathBuf,
+    /// The program as loaded, to start it over on a reset.
+    rom: Vec<u8>,
+    instructions_per_frame: u32,
+    /// Stop after this many frames instead of running until the program exits.
+    frames: Option<u64>,
+    /// Sleep to keep 60 frames per second, headless runs go as fast as they can.
+    paced: bool,
+    muted: bool,
+    movie: Option<MovieSession>,
+    /// `None` if rewinding is off, it is during movies.
+    rewind: Option<RewindBuffer>,
+    screenshots: ScreenshotSettings,
+    capture: Option<Capture>,
+    /// For the WAV of raw frame captures.
+    audio_settings: AudioSettings,
+}
+
+struct ScreenshotSettings {
+    directory: PathBuf,
+    palette: Palette,
+    /// `None` for one image pixel per CHIP-8 pixel, see `capture::Frame`.
+    scale: Option<usize>,
+    /// Frames to take a screenshot after without the hotkey.
+    frames: Vec<u64>,
+}
+
+impl ScreenshotSettings {
+    /// GIFs and raw frames keep one size when the resolution changes, at
+    /// native size that of hires.
+    fn video_scale(&self) -> usize {
+        self.scale.unwrap_or(2)
+    }
+}
+
+/// A movie being recorded or played back.
+struct MovieSession {
+    movie: Movie,
+    /// Where the movie is saved once the run ends, `None` if it is only played.
+    path: Option<PathBuf>,
+    /// The keys come from the movie until it ends or a key press branches off.
+    playing: bool,
+}
+
+impl MovieSession {
+    /// The keys to run `frame` with, `live` being the ones held on the host.
+    fn input(&mut self, frame: u64, live: [bool; 16]) -> [bool; 16] {
+        if self.playing {
+            if frame >= self.movie.len() {
+                self.playing = false;
+                println!("Movie ended at frame {}", frame);
+            } else if self.path.is_some() && live.contains(&true) {
+                self.movie.truncate(frame);
+                self.playing = false;
+                println!("Recording from frame {}", frame);
+            } else {
+                return self.movie.input(frame);
+            }
+        }
+        if self.path.is_some() {
+            self.movie.record(frame, &live);
+        }
+        live
+    }
+
+    fn status(&self, frame: u64) -> String {
+        if self.playing {
+            format!("playing frame {} of {}", frame, self.movie.len())
+        } else if self.path.is_some() {
+            format!("recording frame {}", frame)
+        } else {
+            format!("frame {}", frame)
+        }
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        fs::write(path, self.movie.to_string())
+            .map_err(|err| format!("Could not write {}: {}", path.display(), err))
+    }
+}
+
+/// Gameplay being recorded as a GIF and/or a PNG per frame with the buzzer
+/// as WAV.
+struct Capture {
+    gif: Option<(PathBuf, GifRecorder<BufWriter<File>>)>,
+    /// The directory raw frames and `audio.wav` go to.
+    raw_frames: Option<PathBuf>,
+    audio: AudioRecorder,
+    frame: u64,
+}
+
+impl Capture {
+    fn start(
+        gif: Option<PathBuf>,
+        raw_frames: Option<PathBuf>,
+        screenshots: &ScreenshotSettings,
+    ) -> Result<Capture, String> {
+        let gif = match gif {
+            Some(path) => {
+                let file = File::create(&path)
+                    .map_err(|err| format!("Could not create {}: {}", path.display(), err))?;
+                let recorder = GifRecorder::new(
+                    BufWriter::new(file),
+                    &screenshots.palette,
+                    screenshots.video_scale(),
+                )
+                .map_err(|err| format!("Could not write {}: {}", path.display(), err))?;
+                Some((path, recorder))
+            }
+            None => None,
+        };
+        if let Some(directory) = &raw_frames {
+            fs::create_dir_all(directory)
+                .map_err(|err| format!("Could not create {}: {}", directory.display(), err))?;
+        }
+        Ok(Capture {
+            gif,
+            raw_frames,
+            audio: AudioRecorder::new(),
+            frame: 0,
+        })
+    }
+
+    /// Adds the frame just shown.
+    fn add(
+        &mut self,
+        interpreter: &Interpreter,
+        beeping: bool,
+        pattern: Option<AudioPattern>,
+        screenshots: &ScreenshotSettings,
+    ) -> Result<(), String> {
+        if let Some((path, recorder)) = &mut self.gif {
+            recorder
+                .add_frame(&interpreter.screen)
+                .map_err(|err| format!("Could not write {}: {}", path.display(), err))?;
+        }
+        if let Some(directory) = &self.raw_frames {
+            let path = directory.join(format!("frame_{:06}.png", self.frame));
+            capture::save_png(
+                &interpreter.screen,
+                &screenshots.palette,
+                Some(screenshots.video_scale()),
+                &path,
+            )
+            .map_err(|err| format!("Could not write {}: {}", path.display(), err))?;
+        }
+        self.audio.set_pattern(pattern);
+        self.audio.set_beeping(beeping);
+        self.frame += 1;
+        Ok(())
+    }
+
+    fn finish(self, audio_settings: AudioSettings) -> Result<(), String> {
+        if let Some((path, recorder)) = self.gif {
+            recorder
+                .finish()
+                .map_err(|err| format!("Could not write {}: {}", path.display(), err))?;
+            println!("Saved {}", path.display());
+        }
+        if let Some(directory) = self.raw_frames {
+            let path = directory.join("audio.wav");
+            capture::save_wav(&self.audio, audio_settings, &path)
+                .map_err(|err| format!("Could not write {}: {}", path.display(), err))?;
+            println!("Saved {} frames to {}", self.frame, directory.display());
+        }
+        Ok(())
+    }
+}
+
+/// Pause and speed, as the hotkeys set them.
+#[derive(Default)]
+struct Controls {
+    paused: bool,
+    /// Run one frame although paused.
+    advance: bool,
+    fast_forward: bool,
+    slow_motion: bool,
+    rewinding: bool,
+}
+
+impl Controls {
+    fn frame_duration(&self) -> Duration {
+        let normal = Duration::from_secs(1) / chip_8_emulator::FRAME_RATE;
+        if self.fast_forward {
+            normal / SPEED_FACTOR
+        } else if self.slow_motion {
+            normal * SPEED_FACTOR
+        } else {
+            normal
+        }
+    }
+}
+
+/// Writes the screen to `name` in the screenshot directory.
+fn save_screenshot(interpreter: &Interpreter, session: &Session, name: &str) {
+    let settings = &session.screenshots;
+    let path = settings.directory.join(name);
+    match capture::save_png(
+        &interpreter.screen,
+        &settings.palette,
+        settings.scale,
+        &path,
+    ) {
+        Ok(()) => println!("Saved screenshot {}", path.display()),
+        Err(err) => eprintln!("Could not save {}: {}", path.display(), err),
+    }
+}
+
+/// The ROM's file name without extension, to name files after it.
+fn rom_name(session: &Session) -> String {
+    session
+        .rom_path
+        .file_stem()
+        .map_or("chip8".into(), |stem| stem.to_string_lossy().into_owned())
+}
+
+/// Save states are kept next to the ROM, e.g. `pong.rom.state3`.
+fn state_path(rom_path: &Path, slot: u8) -> String {
+    format!("{}.state{}", rom_path.display(), slot)
+}
+
+fn run(
+    interpreter: &mut Interpreter,
+    display: &mut dyn Display,
+    keypad: &mut dyn Keypad,
+    audio: &mut dyn Audio,
+    debugger: &mut Debugger,
+    session: &mut Session,
+) -> Result<(), EmulatorError> {
+    let mut next_frame = Instant::now();
+    let mut muted = session.muted;
+    let mut slot: u8 = 0;
+    let mut frame: u64 = 0;
+    let mut controls = Controls::default();
+    let mut held = interpreter.input;
+    if let Some(rewind) = &mut session.rewind {
+        rewind.capture(interpreter);
+    }
+
+    while !chip_8_emulator::has_exited(interpreter)
+        && session.frames.is_none_or(|frames| frame < frames)
+    {
+        let running = !controls.paused || controls.advance;
+        controls.advance = false;
+        if running && controls.rewinding {
+            if session
+                .rewind
+                .as_mut()
+                .is_some_and(|rewind| rewind.step_back(interpreter))
+            {
+                display.draw(&interpreter.screen);
+            }
+        } else if running {
+            interpreter.input = match &mut session.movie {
+                Some(movie) => movie.input(frame, held),
+                None => held,
+            };
+            frame += 1;
+            if let Some(movie) = &session.movie {
+                display.show_status(&movie.status(frame));
+            }
+            let redraw = debugger.run_frame(interpreter, session.instructions_per_frame)?;
+            // The movie frame shown with the screen changes every frame.
+            if redraw || session.movie.is_some() {
+                display.draw(&interpreter.screen);
+            }
+            for err in interpreter.skipped_errors.drain(..) {
+                eprintln!("{}", err);
+            }
+            if debugger.has_quit() {
+                break;
+            }
+            if let Some(rewind) = &mut session.rewind {
+                rewind.capture(interpreter);
+            }
+            if session.screenshots.frames.contains(&frame) {
+                let name = format!("{}-frame-{}.png", rom_name(session), frame);
+                save_screenshot(interpreter, session, &name);
+            }
+        }
+        let beeping = chip_8_emulator::is_beeping(interpreter) && running && !controls.rewinding;
+        let pattern = chip_8_emulator::audio_pattern(interpreter);
+        audio.set_pattern(pattern);
+        audio.set_beeping(beeping && !muted);
+        if let (true, Some(capture)) = (running, &mut session.capture) {
+            if let Err(err) = capture.add(interpreter, beeping, pattern, &session.screenshots) {
+                eprintln!("{}, stopped recording", err);
+                session.capture = None;
+            }
+        }
+
+        for hotkey in keypad.poll(&mut held) {
+            match hotkey {
+                Hotkey::Quit => return Ok(()),
+                // The movie would not match the run any more.
+                Hotkey::LoadState | Hotkey::Reset | Hotkey::Reload if session.movie.is_some() => {
+                    eprintln!("Can't load states, reset or reload during a movie")
+                }
+                Hotkey::ToggleMute => muted = !muted,
+                Hotkey::SaveState => {
+                    match save_state_to_file(interpreter, state_path(&session.rom_path, slot)) {
+                        Ok(()) => println!("Saved state to slot {}", slot),
+                        Err(err) => eprintln!("Could not save slot {}: {}", slot, err),
+                    }
+                }
+                Hotkey::LoadState => {
+                    match load_state_from_file(interpreter, state_path(&session.rom_path, slot)) {
+                        Ok(()) => {
+                            println!("Loaded state from slot {}", slot);
+                            display.draw(&interpreter.screen);
+                        }
+                        Err(err) => eprintln!("Could not load slot {}: {}", slot, err),
+                    }
+                }
+                Hotkey::NextSlot => {
+                    slot = (slot + 1) % STATE_SLOTS;
+                    println!("Selected slot {}", slot);
+                }
+                Hotkey::Debug => {
+                    println!("Paused, type help for debugger commands");
+                    debugger.pause();
+                }
+                Hotkey::PreviousSlot => {
+                    slot = (slot + STATE_SLOTS - 1) % STATE_SLOTS;
+                    println!("Selected slot {}", slot);
+                }
+                Hotkey::TogglePause => {
+                    controls.paused = !controls.paused;
+                    println!("{}", if controls.paused { "Paused" } else { "Resumed" });
+                }
+                Hotkey::FrameAdvance => {
+                    controls.paused = true;
+                    controls.advance = true;
+                }
+                Hotkey::Reset => {
+                    chip_8_emulator::reset(interpreter, &session.rom);
+                    display.draw(&interpreter.screen);
+                    println!("Reset");
+                }
+                Hotkey::Reload => {
+                    // Addresses may have moved, so labels and breakpoints start over.
+                    let mut fresh = Debugger::new();
+                    match read_program(&session.rom_path, &mut fresh) {
+                        Ok(rom) => {
+                            session.rom = rom;
+                            *debugger = fresh;
+                            chip_8_emulator::reset(interpreter, &session.rom);
+                            display.draw(&interpreter.screen);
+                            println!("Reloaded {}", session.rom_path.display());
+                        }
+                        Err(err) => eprintln!("Could not reload: {}", err),
+                    }
+                }
+                Hotkey::Screenshot => {
+                    let name = capture::timestamped_name(&rom_name(session), "png");
+                    save_screenshot(interpreter, session, &name);
+                }
+                Hotkey::ToggleCapture => match session.capture.take() {
+                    Some(capture) => {
+                        if let Err(err) = capture.finish(session.audio_settings) {
+                            eprintln!("{}", err);
+                        }
+                    }
+                    None => {
+                        let name = capture::timestamped_name(&rom_name(session), "gif");
+                        let path = session.screenshots.directory.join(name);
+                        match Capture::start(Some(path), None, &session.screenshots) {
+                            Ok(capture) => {
+                                println!("Recording GIF");
+                                session.capture = Some(capture);
+                            }
+                            Err(err) => eprintln!("{}", err),
+                        }
+                    }
+                },
+                Hotkey::FastForward(held) => controls.fast_forward = held,
+                Hotkey::Rewind(held) => controls.rewinding = held && session.rewind.is_some(),
+                Hotkey::ToggleSlowMotion => {
+                    controls.slow_motion = !controls.slow_motion;
+                    println!(
+                        "Slow motion {}",
+                        if controls.slow_motion { "on" } else { "off" }
+                    );
+                }
+            }
+        }
+        if !session.paced {
+            continue;
+        }
+        next_frame += controls.frame_duration();
+        let now = Instant::now();
+        if next_frame > now {
+            std::thread::sleep(next_frame - now);
+        } else {
+            // Running behind, e.g. after the window was dragged. Don't try to catch up.
+            next_frame = now;
+        }
+    }
+    Ok(())
+}
+
+/// Reads a ROM, or compiles it if it is Octo source. The labels and
+/// breakpoints of Octo programs go to `debugger`.
+fn read_program(path: &Path, debugger: &mut Debugger) -> Result<Vec<u8>, String> {
+    if path.extension().is_some_and(|extension| extension == "8o") {
+        let program = octo::compile_file(path).map_err(|err| err.to_string())?;
+        debugger.set_labels(&program.labels);
+        for (_, address) in program.breakpoints {
+            debugger.add_breakpoint(Some(address), None);
+        }
+        return Ok(program.rom);
+    }
+    fs::read(path).map_err(|err| format!("Could not read {}: {}", path.display(), err))
+}
 
-fn main() -> Result<(), String> {
-    let mut interpreter = chip_8_emulator::setup_emulator();
-    chip_8_emulator::load_program(&mut interpreter);
-    let mut cycle_num: u64 = 0;
+/// Layers the config file and the command line options `overrides` on top
+/// of the built in settings.
+fn settings(machine: &MachineArgs, rom: &[u8], overrides: Settings) -> Result<Settings, String> {
+    let config = Config::load(machine.config.as_deref())?;
+    let sha1 = romdb::sha1_hex(rom);
+    let known = database(machine.config.as_deref())?
+        .lookup(&sha1)
+        .map(|entry| Settings::from_entry(&entry))
+        .unwrap_or_default();
+    let settings = Settings::builtin()
+        .merge(config.default.clone())
+        .merge(known)
+        .merge(config.rom_settings(&sha1))
+        .merge(overrides);
+    if settings.scale == Some(0) {
+        return Err("The scale has to be at least 1".to_string());
+    }
+    Ok(settings)
+}
+
+/// The embedded ROM database plus the user's own `programs.json`, if any.
+fn database(config_path: Option<&Path>) -> Result<Database, String> {
+    let mut database = Database::embedded();
+    if let Some(path) = Config::programs_path(config_path).filter(|path| path.exists()) {
+        let json = fs::read_to_string(&path)
+            .map_err(|err| format!("Could not read {}: {}", path.display(), err))?;
+        database
+            .add_programs(&json)
+            .map_err(|err| format!("{}: {}", path.display(), err))?;
+    }
+    Ok(database)
+}
+
+/// Reads a movie, checking it was recorded with `rom`.
+fn load_movie(path: &Path, rom: &[u8]) -> Result<Movie, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|err| format!("Could not read {}: {}", path.display(), err))?;
+    let movie = Movie::parse(&text).map_err(|err| format!("{}: {}", path.display(), err))?;
+    let sha1 = romdb::sha1_hex(rom);
+    if movie.rom_sha1 != sha1 {
+        return Err(format!(
+            "{} was recorded with the ROM with SHA-1 {}, this one is {}",
+            path.display(),
+            movie.rom_sha1,
+            sha1
+        ));
+    }
+    Ok(movie)
+}
 
-    loop {
-        let start = Instant::now();
-        // The rest of the game loop goes here...
-        if emulate(&mut interpreter, cycle_num) {
-            chip_8_emulator::render(&mut interpreter);
+/// The settings a movie was recorded with, which it needs to play back the same.
+fn movie_settings(movie: &Movie) -> Settings {
+    Settings {
+        ips: Some(movie.instructions_per_frame * chip_8_emulator::FRAME_RATE),
+        variant: Some(movie.variant),
+        quirks: Some(movie.quirks),
+        rng: Some(movie.rng),
+        seed: Some(movie.seed),
+        ..Settings::default()
+    }
+}
+
+fn start(settings: &Settings, rom: &[u8]) -> Interpreter {
+    let mut interpreter = chip_8_emulator::setup_emulator(
+        settings.variant.unwrap_or_default(),
+        settings.quirks.unwrap_or_default(),
+    );
+    let mode = settings.rng.unwrap_or_default();
+    let rng = match settings.seed {
+        Some(seed) => Rng::new(mode, seed),
+        None => Rng::from_time(mode),
+    };
+    chip_8_emulator::set_rng(&mut interpreter, rng);
+    interpreter.error_policy = settings.on_error.unwrap_or_default();
+    chip_8_emulator::load_rom(&mut interpreter, rom);
+    interpreter
+}
+
+fn instructions_per_frame(settings: &Settings) -> u32 {
+    match settings.ips {
+        Some(ips) => (ips / chip_8_emulator::FRAME_RATE).max(1),
+        None => chip_8_emulator::DEFAULT_INSTRUCTIONS_PER_FRAME,
+    }
+}
+
+fn print_config(machine: &MachineArgs, rom: &[u8], settings: &Settings) -> Result<(), String> {
+    let config_path = match &machine.config {
+        Some(path) => Some(path.clone()),
+        None => Config::default_path(),
+    };
+    println!("# ROM: {}", machine.rom.display());
+    println!("# SHA-1: {}", romdb::sha1_hex(rom));
+    if let Some(path) = config_path {
+        println!("# Config file: {}", path.display());
+    }
+    print!(
+        "{}",
+        toml::to_string(settings).map_err(|err| err.to_string())?
+    );
+    Ok(())
+}
+
+fn run_rom(args: &RunArgs) -> Result<(), String> {
+    let mut debugger = Debugger::new();
+    let rom = read_program(&args.machine.rom, &mut debugger)?;
+    let played = match &args.play {
+        Some(path) => Some(load_movie(path, &rom)?),
+        None => None,
+    };
+    let mut overrides = args.settings();
+    if let Some(movie) = &played {
+        overrides = overrides.merge(movie_settings(movie));
+    }
+    let mut settings = settings(&args.machine, &rom, overrides)?;
+    if args.print_config {
+        return print_config(&args.machine, &rom, &settings);
+    }
+    // A recording can only be played back with the seed it ran with.
+    if args.record.is_some() && settings.seed.is_none() {
+        settings.seed = Some(Rng::time_seed());
+    }
+    let movie = match (played, &args.record) {
+        (Some(movie), _) => Some(MovieSession {
+            movie,
+            path: args.play.clone().filter(|_| args.read_write),
+            playing: true,
+        }),
+        (None, Some(path)) => Some(MovieSession {
+            movie: Movie::new(
+                romdb::sha1_hex(&rom),
+                settings.variant.unwrap_or_default(),
+                settings.quirks.unwrap_or_default(),
+                instructions_per_frame(&settings),
+                settings.rng.unwrap_or_default(),
+                settings.seed.unwrap_or_default(),
+            ),
+            path: Some(path.clone()),
+            playing: false,
+        }),
+        (None, None) => None,
+    };
+    let mut interpreter = start(&settings, &rom);
+    if let Some(path) = &args.load_state {
+        load_state_from_file(&mut interpreter, path)
+            .map_err(|err| format!("Could not load {}: {}", path.display(), err))?;
+    }
+    let screenshots = ScreenshotSettings {
+        directory: match &settings.screenshots.directory {
+            Some(directory) => directory.clone(),
+            None => args
+                .machine
+                .rom
+                .parent()
+                .unwrap_or(Path::new(""))
+                .to_path_buf(),
+        },
+        palette: settings.palette.unwrap_or_default(),
+        scale: settings
+            .scale
+            .filter(|_| !settings.screenshots.native.unwrap_or_default()),
+        frames: args.screenshot_at.clone(),
+    };
+    let capture = match (&args.gif, &args.raw_frames) {
+        (None, None) => None,
+        (gif, raw_frames) => Some(Capture::start(
+            gif.clone(),
+            raw_frames.clone(),
+            &screenshots,
+        )?),
+    };
+    let mut session = Session {
+        rom_path: args.machine.rom.clone(),
+        rom,
+        instructions_per_frame: instructions_per_frame(&settings),
+        frames: args.frames,
+        paced: !args.headless,
+        muted: settings.mute.unwrap_or_default(),
+        rewind: settings.rewind_buffer().filter(|_| movie.is_none()),
+        screenshots,
+        capture,
+        audio_settings: settings.audio_settings(),
+        movie,
+    };
+    let result = if args.headless {
+        run(
+            &mut interpreter,
+            &mut NullFrontend,
+            &mut NullFrontend,
+            &mut NullFrontend,
+            &mut debugger,
+            &mut session,
+        )
+        .map_err(|err| err.to_string())
+    } else {
+        run_windowed(&settings, &mut interpreter, &mut debugger, &mut session)
+    };
+    // Saved even if the program crashed, that may be what the movie is for.
+    let saved = session.movie.as_ref().map_or(Ok(()), MovieSession::save);
+    let captured = session
+        .capture
+        .take()
+        .map_or(Ok(()), |capture| capture.finish(session.audio_settings));
+    result.and(saved).and(captured)
+}
+
+#[cfg(feature = "sdl")]
+fn run_windowed(
+    settings: &Settings,
+    interpreter: &mut Interpreter,
+    debugger: &mut Debugger,
+    session: &mut Session,
+) -> Result<(), String> {
+    use chip_8_emulator::sdl;
+
+    let bindings = sdl::resolve_keymap(&settings.keymap()?)?;
+    let scale = settings.scale.unwrap_or(sdl::DEFAULT_SCALE);
+    let (mut display, mut keypad, mut audio) =
+        sdl::setup_frontend(settings.audio_settings(), scale, bindings);
+    if let Some(palette) = settings.palette {
+        display.palette = palette;
+    }
+    run(
+        interpreter,
+        &mut display,
+        &mut keypad,
+        &mut audio,
+        debugger,
+        session,
+    )
+    .map_err(|err| err.to_string())
+}
+
+#[cfg(not(feature = "sdl"))]
+fn run_windowed(
+    _settings: &Settings,
+    _interpreter: &mut Interpreter,
+    _debugger: &mut Debugger,
+    _session: &mut Session,
+) -> Result<(), String> {
+    Err("Built without SDL, only --headless runs are possible".to_string())
+}
+
+/// The screen as text, `.` for dark pixels and `#`, `+` or `@` for pixels lit
+/// on plane 1, plane 2 or both.
+fn screen_text(screen: &Screen) -> String {
+    let mut text = String::new();
+    for y in 0..screen.height() {
+        for x in 0..screen.width() {
+            text.push(match screen.get(x, y) {
+                0 => '.',
+                1 => '#',
+                2 => '+',
+                _ => '@',
+            });
+        }
+        text.push('\n');
+    }
+    text
+}
+
+/// Runs a ROM headless for a number of frames and prints the screen, e.g. to
+/// check test ROMs.
+fn test(args: &TestArgs) -> Result<(), String> {
+    let rom = read_program(&args.machine.rom, &mut Debugger::new())?;
+    let movie = match &args.play {
+        Some(path) => Some(load_movie(path, &rom)?),
+        None => None,
+    };
+    let mut overrides = args.machine.settings();
+    if let Some(movie) = &movie {
+        overrides = overrides.merge(movie_settings(movie));
+    }
+    let settings = settings(&args.machine, &rom, overrides)?;
+    let mut interpreter = start(&settings, &rom);
+    let instructions_per_frame = instructions_per_frame(&settings);
+    for frame in 0..args.frames {
+        if chip_8_emulator::has_exited(&interpreter) {
+            break;
         }
+        if let Some(movie) = &movie {
+            interpreter.input = movie.input(frame);
+        }
+        chip_8_emulator::run_frame(&mut interpreter, instructions_per_frame)
+            .map_err(|err| err.to_string())?;
+        for err in interpreter.skipped_errors.drain(..) {
+            eprintln!("{}", err);
+        }
+    }
+    let screen = screen_text(&interpreter.screen);
+    print!("{}", screen);
+    if let Some(path) = &args.expect {
+        let expected = fs::read_to_string(path)
+            .map_err(|err| format!("Could not read {}: {}", path.display(), err))?;
+        if expected != screen {
+            return Err(format!("Screen differs from {}", path.display()));
+        }
+    }
+    Ok(())
+}
+
+fn info(path: &Path, config_path: Option<&Path>) -> Result<(), String> {
+    let mut debugger = Debugger::new();
+    let rom = read_program(path, &mut debugger)?;
+    let lines = decode_rom(&rom, PROGRAM_START);
+    let instructions: Vec<_> = lines.iter().filter_map(|line| line.instruction).collect();
+    // Data decodes as instructions too, so this is only a guess.
+    let variant = [Variant::Chip8, Variant::SuperChip, Variant::XoChip]
+        .into_iter()
+        .find(|&variant| {
+            instructions
+                .iter()
+                .all(|instruction| instruction.is_supported_by(variant))
+        })
+        .unwrap_or(Variant::XoChip);
+    println!("File: {}", path.display());
+    println!("Size: {} bytes", rom.len());
+    let sha1 = romdb::sha1_hex(&rom);
+    println!("SHA-1: {}", sha1);
+    if let Some(entry) = database(config_path)?.lookup(&sha1) {
+        println!("Title: {}", entry.title);
+        if !entry.authors.is_empty() {
+            println!("Authors: {}", entry.authors.join(", "));
+        }
+        println!("Platform: {}", entry.platform);
+    }
+    println!(
+        "Words decoding as instructions: {} of {}",
+        instructions.len(),
+        lines.len()
+    );
+    println!("Probable variant: {}", variant.name());
+    Ok(())
+}
+
+fn disasm(rom_path: &Path) -> Result<(), String> {
+    let rom = fs::read(rom_path)
+        .map_err(|err| format!("Could not read {}: {}", rom_path.display(), err))?;
+    print!(
+        "{}",
+        chip_8_emulator::disasm::disassemble(&rom, PROGRAM_START)
+    );
+    Ok(())
+}
+
+fn asm(source_path: &Path, rom_path: Option<&Path>) -> Result<(), String> {
+    let rom = chip_8_emulator::asm::assemble_file(source_path).map_err(|err| err.to_string())?;
+    let rom_path = match rom_path {
+        Some(path) => path.to_path_buf(),
+        None => source_path.with_extension("ch8"),
+    };
+    fs::write(&rom_path, rom)
+        .map_err(|err| format!("Could not write {}: {}", rom_path.display(), err))
+}
 
-        chip_8_emulator::handle_input(&mut interpreter);
-        let elapsed = start.elapsed();
-        if elapsed.as_nanos() < 2_000_000 {
-            std::thread::sleep(Duration::new(
-                0,
-                (2_000_000 - elapsed.as_nanos()).try_into().unwrap(),
-            ));
+fn main() -> ExitCode {
+    let result = match cli::parse() {
+        Command::Run(args) => run_rom(&args),
+        Command::Disasm { rom } => disasm(&rom),
+        Command::Asm { source, output } => asm(&source, output.as_deref()),
+        Command::Test(args) => test(&args),
+        Command::Info { rom, config } => info(&rom, config.as_deref()),
+    };
+    // Printed with Display, config parse errors span several lines.
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            ExitCode::FAILURE
         }
-        cycle_num += 1;
     }
 }
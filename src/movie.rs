@@ -0,0 +1,163 @@
+use crate::{Quirks, RngMode, Variant};
+use std::fmt;
+
+const HEADER: &str = "chip-8 movie 1";
+
+/// The keys held on every frame of a run, plus what is needed to run it the
+/// same way again. Only frames the keys changed on are stored.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Movie {
+    /// SHA-1 of the ROM, see `romdb::sha1_hex`.
+    pub rom_sha1: String,
+    pub variant: Variant,
+    pub quirks: Quirks,
+    pub instructions_per_frame: u32,
+    pub rng: RngMode,
+    pub seed: u64,
+    /// Frames with the keys held from then on, key 0 in the lowest bit.
+    changes: Vec<(u64, u16)>,
+    /// Frames recorded, the keys may have last changed long before the end.
+    length: u64,
+}
+
+impl Movie {
+    pub fn new(
+        rom_sha1: String,
+        variant: Variant,
+        quirks: Quirks,
+        instructions_per_frame: u32,
+        rng: RngMode,
+        seed: u64,
+    ) -> Movie {
+        Movie {
+            rom_sha1,
+            variant,
+            quirks,
+            instructions_per_frame,
+            rng,
+            seed,
+            changes: Vec::new(),
+            length: 0,
+        }
+    }
+
+    pub fn len(&self) -> u64 {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    /// Notes the keys held on `frame`, which has to come after all frames
+    /// recorded so far.
+    pub fn record(&mut self, frame: u64, input: &[bool; 16]) {
+        let mask = to_mask(input);
+        let last = self.changes.last().map_or(0, |&(_, mask)| mask);
+        if mask != last {
+            self.changes.push((frame, mask));
+        }
+        self.length = self.length.max(frame + 1);
+    }
+
+    /// The keys held on `frame`.
+    pub fn input(&self, frame: u64) -> [bool; 16] {
+        let index = self.changes.partition_point(|&(start, _)| start <= frame);
+        let mask = match index {
+            0 => 0,
+            _ => self.changes[index - 1].1,
+        };
+        std::array::from_fn(|key| mask & (1 << key) != 0)
+    }
+
+    /// Forgets `frame` and everything after it, to record a different future.
+    pub fn truncate(&mut self, frame: u64) {
+        self.changes.retain(|&(start, _)| start < frame);
+        self.length = self.length.min(frame);
+    }
+
+    /// Reads a movie in the format `Display` writes.
+    pub fn parse(text: &str) -> Result<Movie, String> {
+        let mut lines = text.lines().enumerate();
+        if lines.next().map(|(_, line)| line.trim()) != Some(HEADER) {
+            return Err("Not a movie file".to_string());
+        }
+        let mut fields: Vec<(&str, &str)> = Vec::new();
+        let mut changes = Vec::new();
+        for (index, line) in lines {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = || format!("Line {}: can't read {}", index + 1, line);
+            let (first, rest) = line.split_once(' ').ok_or_else(error)?;
+            match first.parse::<u64>() {
+                Ok(frame) => {
+                    let mask = u16::from_str_radix(rest.trim(), 16).map_err(|_| error())?;
+                    changes.push((frame, mask));
+                }
+                Err(_) => fields.push((first, rest.trim())),
+            }
+        }
+        let field = |name: &str| {
+            fields
+                .iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| *value)
+                .ok_or_else(|| format!("Movie has no {}", name))
+        };
+        let number = |name: &str| {
+            field(name)?
+                .parse::<u64>()
+                .map_err(|_| format!("Movie has an invalid {}", name))
+        };
+        if !changes.is_sorted_by(|a: &(u64, u16), b| a.0 < b.0) {
+            return Err("Movie frames are out of order".to_string());
+        }
+        let movie = Movie {
+            rom_sha1: field("rom")?.to_ascii_lowercase(),
+            variant: field("variant")?.parse()?,
+            quirks: field("quirks")?.parse()?,
+            instructions_per_frame: number("instructions-per-frame")? as u32,
+            rng: field("rng")?.parse()?,
+            seed: number("seed")?,
+            length: number("frames")?,
+            changes,
+        };
+        if movie
+            .changes
+            .last()
+            .is_some_and(|&(frame, _)| frame >= movie.length)
+        {
+            return Err("Movie has keys after its last frame".to_string());
+        }
+        Ok(movie)
+    }
+}
+
+/// A text file: a header, the settings, then a frame number and the held keys
+/// as hex on every line the keys changed on.
+impl fmt::Display for Movie {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", HEADER)?;
+        writeln!(f, "rom {}", self.rom_sha1)?;
+        writeln!(f, "variant {}", self.variant)?;
+        writeln!(f, "quirks {}", self.quirks)?;
+        writeln!(f, "instructions-per-frame {}", self.instructions_per_frame)?;
+        writeln!(f, "rng {}", self.rng)?;
+        writeln!(f, "seed {}", self.seed)?;
+        writeln!(f, "frames {}", self.length)?;
+        for (frame, mask) in &self.changes {
+            writeln!(f, "{} {:04x}", frame, mask)?;
+        }
+        Ok(())
+    }
+}
+
+fn to_mask(input: &[bool; 16]) -> u16 {
+    input
+        .iter()
+        .enumerate()
+        .filter(|(_, &pressed)| pressed)
+        .fold(0, |mask, (key, _)| mask | 1 << key)
+}
@@ -0,0 +1,1065 @@
+use crate::error::AsmError;
+use crate::PROGRAM_START;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::f64::consts::{E, PI};
+use std::fs;
+use std::path::Path;
+
+/// Stops runaway recursive macros.
+const MAX_MACRO_EXPANSIONS: usize = 100_000;
+
+/// A compiled Octo program.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Program {
+    /// The bytes to load at `PROGRAM_START`.
+    pub rom: Vec<u8>,
+    pub labels: BTreeMap<String, u16>,
+    /// The names and addresses of `:breakpoint` directives.
+    pub breakpoints: Vec<(String, u16)>,
+}
+
+/// Compiles Octo source. Supported are labels (`: name`), `:const`, `:alias`,
+/// `:macro`, `:calc`, `:byte`, `:org`, `:unpack`, `:breakpoint`, all
+/// CHIP-8, SUPER-CHIP and XO-CHIP statements, `if ... then`,
+/// `if ... begin ... else ... end` and `loop ... while ... again`.
+pub fn compile(source: &str) -> Result<Program, AsmError> {
+    Compiler::new(tokenize(source, None)).run()
+}
+
+pub fn compile_file(path: impl AsRef<Path>) -> Result<Program, AsmError> {
+    let path = path.as_ref();
+    let file = path.display().to_string();
+    let source = fs::read_to_string(path).map_err(|err| AsmError {
+        file: Some(file.clone()),
+        line: 0,
+        column: 0,
+        message: err.to_string(),
+    })?;
+    Compiler::new(tokenize(&source, Some(&file))).run()
+}
+
+#[derive(Clone, Debug)]
+struct Token {
+    text: String,
+    file: Option<String>,
+    line: usize,
+    column: usize,
+}
+
+impl Token {
+    fn error(&self, message: impl Into<String>) -> AsmError {
+        AsmError {
+            file: self.file.clone(),
+            line: self.line,
+            column: self.column,
+            message: message.into(),
+        }
+    }
+}
+
+/// Splits `source` at whitespace, dropping `#` comments.
+fn tokenize(source: &str, file: Option<&str>) -> VecDeque<Token> {
+    let mut tokens = VecDeque::new();
+    for (index, line) in source.lines().enumerate() {
+        let code = line.split('#').next().unwrap_or("");
+        let mut start = None;
+        for (position, c) in code.char_indices().chain([(code.len(), ' ')]) {
+            match (start, c.is_whitespace()) {
+                (None, false) => start = Some(position),
+                (Some(begin), true) => {
+                    tokens.push_back(Token {
+                        text: code[begin..position].to_string(),
+                        file: file.map(str::to_string),
+                        line: index + 1,
+                        column: begin + 1,
+                    });
+                    start = None;
+                }
+                _ => (),
+            }
+        }
+    }
+    tokens
+}
+
+struct Macro {
+    parameters: Vec<String>,
+    body: Vec<Token>,
+}
+
+/// How an address that is only known later goes into the instruction at
+/// `address`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum FixupKind {
+    /// The low 12 bits of the opcode, e.g. `jump`.
+    Address,
+    /// The whole word after `F000`.
+    Long,
+    /// The low byte of the opcode gets the upper nibble of the address.
+    UnpackHigh,
+    /// The low byte of the opcode gets the low byte of the address.
+    UnpackLow,
+}
+
+struct Fixup {
+    token: Token,
+    address: u16,
+    kind: FixupKind,
+}
+
+enum Block {
+    /// `loop` at `start` with the jumps of its `while`s.
+    Loop { start: u16, exits: Vec<u16> },
+    /// `if ... begin` whose jump at `jump` goes to the `else` or `end`.
+    If { jump: u16, has_else: bool },
+}
+
+/// The test of `if` and `while`: instructions computing it followed by an
+/// instruction skipping the next one unless the condition holds.
+struct Condition {
+    setup: Vec<u16>,
+    skip_unless: u16,
+    skip_if: u16,
+}
+
+/// A value that can be a label defined further down.
+enum Value {
+    Known(i64),
+    Forward(Token),
+}
+
+struct Compiler {
+    tokens: VecDeque<Token>,
+    rom: Vec<u8>,
+    /// Where the next byte goes, 0x10000 once the last address is used.
+    here: u32,
+    labels: BTreeMap<String, u16>,
+    constants: HashMap<String, f64>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, Macro>,
+    fixups: Vec<Fixup>,
+    blocks: Vec<(Token, Block)>,
+    breakpoints: Vec<(String, u16)>,
+    expansions: usize,
+    /// The last token taken, for errors at the end of the source.
+    last: Token,
+}
+
+impl Compiler {
+    fn new(tokens: VecDeque<Token>) -> Compiler {
+        Compiler {
+            tokens,
+            rom: Vec::new(),
+            here: PROGRAM_START as u32,
+            labels: BTreeMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+            fixups: Vec::new(),
+            blocks: Vec::new(),
+            breakpoints: Vec::new(),
+            expansions: 0,
+            last: Token {
+                text: String::new(),
+                file: None,
+                line: 1,
+                column: 1,
+            },
+        }
+    }
+
+    fn run(mut self) -> Result<Program, AsmError> {
+        while !self.tokens.is_empty() {
+            self.statement()?;
+        }
+        if let Some((token, _)) = self.blocks.last() {
+            return Err(token.error(format!("{} is never closed", token.text)));
+        }
+        for fixup in std::mem::take(&mut self.fixups) {
+            let target = *self.labels.get(&fixup.token.text).ok_or_else(|| {
+                fixup
+                    .token
+                    .error(format!("Undefined name {}", fixup.token.text))
+            })?;
+            self.patch(&fixup.token, fixup.address, target as i64, fixup.kind)?;
+        }
+        Ok(Program {
+            rom: self.rom,
+            labels: self.labels,
+            breakpoints: self.breakpoints,
+        })
+    }
+
+    fn next(&mut self) -> Result<Token, AsmError> {
+        match self.tokens.pop_front() {
+            Some(token) => {
+                self.last = token.clone();
+                Ok(token)
+            }
+            None => Err(self.last.error("Unexpected end of source")),
+        }
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.front().map(|token| token.text.as_str())
+    }
+
+    fn expect(&mut self, text: &str) -> Result<Token, AsmError> {
+        let token = self.next()?;
+        if token.text != text {
+            return Err(token.error(format!("Expected {} but found {}", text, token.text)));
+        }
+        Ok(token)
+    }
+
+    fn statement(&mut self) -> Result<(), AsmError> {
+        let token = self.next()?;
+        match token.text.as_str() {
+            ":" => {
+                let name = self.name()?;
+                let address = self.address(&name)?;
+                self.define_label(&name, address)?;
+            }
+            ":const" => {
+                let name = self.name()?;
+                let value = self.number()?;
+                self.define_constant(&name, value as f64)?;
+            }
+            ":alias" => {
+                let name = self.name()?;
+                let register = self.register()?;
+                self.aliases.insert(name.text, register);
+            }
+            ":macro" => {
+                let name = self.name()?;
+                let mut parameters = Vec::new();
+                while self.peek().is_some_and(|text| text != "{") {
+                    parameters.push(self.next()?.text);
+                }
+                let body = self.braces()?;
+                self.macros.insert(name.text, Macro { parameters, body });
+            }
+            ":calc" => {
+                let name = self.name()?;
+                let tokens = self.braces()?;
+                let value = self.calculate(&token, &tokens)?;
+                self.define_constant(&name, value)?;
+            }
+            ":byte" => {
+                let value = self.number()?;
+                self.emit_byte(&token, value)?;
+            }
+            ":org" => {
+                let address = self.number()?;
+                if !(PROGRAM_START as i64..=0xFFFF).contains(&address) {
+                    return Err(token.error(format!("Can't place code at {:#x}", address)));
+                }
+                self.here = address as u32;
+            }
+            ":unpack" => {
+                let nibble = self.number()?;
+                let nibble = self.ranged(&token, nibble, 0, 0xF)? as u16;
+                let target = self.value()?;
+                self.emit_opcode(0x6000 | nibble << 4)?;
+                self.emit_opcode(0x6100)?;
+                match target {
+                    Value::Known(address) => {
+                        self.patch(
+                            &token,
+                            (self.here - 4) as u16,
+                            address,
+                            FixupKind::UnpackHigh,
+                        )?;
+                        self.patch(
+                            &token,
+                            (self.here - 2) as u16,
+                            address,
+                            FixupKind::UnpackLow,
+                        )?;
+                    }
+                    Value::Forward(name) => {
+                        self.fixups.push(Fixup {
+                            token: name.clone(),
+                            address: (self.here - 4) as u16,
+                            kind: FixupKind::UnpackHigh,
+                        });
+                        self.fixups.push(Fixup {
+                            token: name,
+                            address: (self.here - 2) as u16,
+                            kind: FixupKind::UnpackLow,
+                        });
+                    }
+                }
+            }
+            ":breakpoint" => {
+                let name = self.name()?;
+                let address = self.address(&name)?;
+                self.breakpoints.push((name.text, address));
+            }
+            ";" | "return" => self.emit_opcode(0x00EE)?,
+            "clear" => self.emit_opcode(0x00E0)?,
+            "scroll-right" => self.emit_opcode(0x00FB)?,
+            "scroll-left" => self.emit_opcode(0x00FC)?,
+            "exit" => self.emit_opcode(0x00FD)?,
+            "lores" => self.emit_opcode(0x00FE)?,
+            "hires" => self.emit_opcode(0x00FF)?,
+            "audio" => self.emit_opcode(0xF002)?,
+            "scroll-down" => {
+                let rows = self.number()?;
+                let rows = self.ranged(&token, rows, 0, 0xF)? as u16;
+                self.emit_opcode(0x00C0 | rows)?;
+            }
+            "plane" => {
+                let planes = self.number()?;
+                let planes = self.ranged(&token, planes, 0, 0x3)? as u16;
+                self.emit_opcode(0xF001 | planes << 8)?;
+            }
+            "bcd" => self.register_opcode(0xF033)?,
+            "saveflags" => self.register_opcode(0xF075)?,
+            "loadflags" => self.register_opcode(0xF085)?,
+            "save" | "load" => {
+                let x = self.register()? as u16;
+                if self.peek() == Some("-") {
+                    self.next()?;
+                    let y = self.register()? as u16;
+                    let n = if token.text == "save" { 0x2 } else { 0x3 };
+                    self.emit_opcode(0x5000 | x << 8 | y << 4 | n)?;
+                } else {
+                    let nn = if token.text == "save" { 0x55 } else { 0x65 };
+                    self.emit_opcode(0xF000 | x << 8 | nn)?;
+                }
+            }
+            "sprite" => {
+                let x = self.register()? as u16;
+                let y = self.register()? as u16;
+                let rows = self.number()?;
+                let rows = self.ranged(&token, rows, 0, 0xF)? as u16;
+                self.emit_opcode(0xD000 | x << 8 | y << 4 | rows)?;
+            }
+            "jump" => self.address_opcode(0x1000)?,
+            "jump0" => self.address_opcode(0xB000)?,
+            "native" => self.address_opcode(0x0000)?,
+            "i" => {
+                let operator = self.next()?;
+                match operator.text.as_str() {
+                    ":=" => match self.peek() {
+                        Some("long") => {
+                            self.next()?;
+                            self.emit_opcode(0xF000)?;
+                            self.emit_opcode(0x0000)?;
+                            self.fill_address(&token, (self.here - 2) as u16, FixupKind::Long)?;
+                        }
+                        Some("hex") => {
+                            self.next()?;
+                            self.register_opcode(0xF029)?;
+                        }
+                        Some("bighex") => {
+                            self.next()?;
+                            self.register_opcode(0xF030)?;
+                        }
+                        _ => self.address_opcode(0xA000)?,
+                    },
+                    "+=" => self.register_opcode(0xF01E)?,
+                    _ => {
+                        return Err(operator.error(format!("Unknown operator i {}", operator.text)))
+                    }
+                }
+            }
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let nn = match token.text.as_str() {
+                    "delay" => 0x15,
+                    "buzzer" => 0x18,
+                    _ => 0x3A,
+                };
+                self.register_opcode(0xF000 | nn)?;
+            }
+            "if" => {
+                let condition = self.condition()?;
+                let keyword = self.next()?;
+                match keyword.text.as_str() {
+                    "then" => {
+                        self.emit_condition(&condition, false)?;
+                        self.statement()?;
+                    }
+                    "begin" => {
+                        self.emit_condition(&condition, true)?;
+                        self.emit_opcode(0x1000)?;
+                        self.blocks.push((
+                            token,
+                            Block::If {
+                                jump: (self.here - 2) as u16,
+                                has_else: false,
+                            },
+                        ));
+                    }
+                    _ => return Err(keyword.error("Expected then or begin")),
+                }
+            }
+            "else" => match self.blocks.pop() {
+                Some((
+                    start,
+                    Block::If {
+                        jump,
+                        has_else: false,
+                    },
+                )) => {
+                    self.emit_opcode(0x1000)?;
+                    self.patch(&token, jump, self.here as i64, FixupKind::Address)?;
+                    self.blocks.push((
+                        start,
+                        Block::If {
+                            jump: (self.here - 2) as u16,
+                            has_else: true,
+                        },
+                    ));
+                }
+                _ => return Err(token.error("else without if ... begin")),
+            },
+            "end" => match self.blocks.pop() {
+                Some((_, Block::If { jump, .. })) => {
+                    self.patch(&token, jump, self.here as i64, FixupKind::Address)?;
+                }
+                _ => return Err(token.error("end without if ... begin")),
+            },
+            "loop" => {
+                let start = self.address(&token)?;
+                self.blocks.push((
+                    token,
+                    Block::Loop {
+                        start,
+                        exits: Vec::new(),
+                    },
+                ));
+            }
+            "while" => {
+                let condition = self.condition()?;
+                let here = self.here;
+                match self
+                    .blocks
+                    .iter_mut()
+                    .rev()
+                    .find_map(|(_, block)| match block {
+                        Block::Loop { exits, .. } => Some(exits),
+                        Block::If { .. } => None,
+                    }) {
+                    Some(exits) => exits.push((here + condition.setup.len() as u32 * 2 + 2) as u16),
+                    None => return Err(token.error("while outside of loop")),
+                }
+                self.emit_condition(&condition, true)?;
+                self.emit_opcode(0x1000)?;
+            }
+            "again" => match self.blocks.pop() {
+                Some((_, Block::Loop { start, exits })) => {
+                    self.emit_opcode(0x1000 | start)?;
+                    for exit in exits {
+                        self.patch(&token, exit, self.here as i64, FixupKind::Address)?;
+                    }
+                }
+                _ => return Err(token.error("again without loop")),
+            },
+            _ => {
+                if let Some(x) = self.register_name(&token.text) {
+                    return self.assignment(x);
+                }
+                if self.macros.contains_key(&token.text) {
+                    return self.expand(&token);
+                }
+                // Bare numbers and constants are data.
+                if !self.labels.contains_key(&token.text) {
+                    if let Some(number) = self.literal(&token.text) {
+                        return self.emit_byte(&token, number);
+                    }
+                }
+                if token.text.starts_with(':') || token.text == "{" {
+                    return Err(token.error(format!("Unknown directive {}", token.text)));
+                }
+                // Everything else is a call of a label, maybe one defined later.
+                self.tokens.push_front(token);
+                self.address_opcode(0x2000)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The statements starting with a register, `vx := ...`, `vx += ...` etc.
+    fn assignment(&mut self, x: u8) -> Result<(), AsmError> {
+        let x = x as u16;
+        let operator = self.next()?;
+        let opcode_xy = |n: u16, y: u8| 0x8000 | x << 8 | (y as u16) << 4 | n;
+        let register = self.peek().and_then(|text| self.register_name(text));
+        match (operator.text.as_str(), register) {
+            (":=", Some(y)) => {
+                self.next()?;
+                self.emit_opcode(opcode_xy(0x0, y))?;
+            }
+            (":=", None) => match self.peek() {
+                Some("key") => {
+                    self.next()?;
+                    self.emit_opcode(0xF00A | x << 8)?;
+                }
+                Some("delay") => {
+                    self.next()?;
+                    self.emit_opcode(0xF007 | x << 8)?;
+                }
+                Some("random") => {
+                    self.next()?;
+                    let mask = self.byte(&operator)?;
+                    self.emit_opcode(0xC000 | x << 8 | mask as u16)?;
+                }
+                _ => {
+                    let value = self.byte(&operator)?;
+                    self.emit_opcode(0x6000 | x << 8 | value as u16)?;
+                }
+            },
+            ("+=", None) => {
+                let value = self.byte(&operator)?;
+                self.emit_opcode(0x7000 | x << 8 | value as u16)?;
+            }
+            ("-=", None) => {
+                let value = self.byte(&operator)?;
+                self.emit_opcode(0x7000 | x << 8 | value.wrapping_neg() as u16)?;
+            }
+            (operator_text, Some(y)) => {
+                let n = match operator_text {
+                    "|=" => 0x1,
+                    "&=" => 0x2,
+                    "^=" => 0x3,
+                    "+=" => 0x4,
+                    "-=" => 0x5,
+                    ">>=" => 0x6,
+                    "=-" => 0x7,
+                    "<<=" => 0xE,
+                    _ => return Err(operator.error(format!("Unknown operator {}", operator_text))),
+                };
+                self.next()?;
+                self.emit_opcode(opcode_xy(n, y))?;
+            }
+            (operator_text, None) => {
+                return Err(operator.error(format!(
+                    "Unknown operator {} or missing register",
+                    operator_text
+                )))
+            }
+        }
+        Ok(())
+    }
+
+    fn condition(&mut self) -> Result<Condition, AsmError> {
+        let x = self.register()? as u16;
+        let operator = self.next()?;
+        let simple = |skip_unless: u16, skip_if: u16| Condition {
+            setup: Vec::new(),
+            skip_unless,
+            skip_if,
+        };
+        match operator.text.as_str() {
+            "key" => return Ok(simple(0xE0A1 | x << 8, 0xE09E | x << 8)),
+            "-key" => return Ok(simple(0xE09E | x << 8, 0xE0A1 | x << 8)),
+            _ => (),
+        }
+        let rhs = match self.peek().and_then(|text| self.register_name(text)) {
+            Some(y) => {
+                self.next()?;
+                Ok(y as u16)
+            }
+            None => Err(self.byte(&operator)? as u16),
+        };
+        let condition = match (operator.text.as_str(), rhs) {
+            ("==", Err(nn)) => simple(0x4000 | x << 8 | nn, 0x3000 | x << 8 | nn),
+            ("!=", Err(nn)) => simple(0x3000 | x << 8 | nn, 0x4000 | x << 8 | nn),
+            ("==", Ok(y)) => simple(0x9000 | x << 8 | y << 4, 0x5000 | x << 8 | y << 4),
+            ("!=", Ok(y)) => simple(0x5000 | x << 8 | y << 4, 0x9000 | x << 8 | y << 4),
+            (comparison @ ("<" | ">" | "<=" | ">="), rhs) => {
+                // VF := rhs, then VF -= VX or VF =- VX leaves the carry in VF.
+                let load = match rhs {
+                    Ok(y) => 0x8F00 | y << 4,
+                    Err(nn) => 0x6F00 | nn,
+                };
+                let subtract = match comparison {
+                    ">" | "<=" => 0x8F05 | x << 4,
+                    _ => 0x8F07 | x << 4,
+                };
+                // VF is 0 exactly when `>` and `<` hold.
+                let (skip_unless, skip_if) = match comparison {
+                    ">" | "<" => (0x4F00, 0x3F00),
+                    _ => (0x3F00, 0x4F00),
+                };
+                Condition {
+                    setup: vec![load, subtract],
+                    skip_unless,
+                    skip_if,
+                }
+            }
+            _ => return Err(operator.error(format!("Unknown comparison {}", operator.text))),
+        };
+        Ok(condition)
+    }
+
+    /// Emits `condition` so the next instruction only runs if it holds, or
+    /// with `inverted` only if it does not.
+    fn emit_condition(&mut self, condition: &Condition, inverted: bool) -> Result<(), AsmError> {
+        for &opcode in &condition.setup {
+            self.emit_opcode(opcode)?;
+        }
+        self.emit_opcode(if inverted {
+            condition.skip_if
+        } else {
+            condition.skip_unless
+        })
+    }
+
+    fn expand(&mut self, name: &Token) -> Result<(), AsmError> {
+        self.expansions += 1;
+        if self.expansions > MAX_MACRO_EXPANSIONS {
+            return Err(name.error("Too many macro expansions, is a macro recursive?"));
+        }
+        let parameters = self.macros[&name.text].parameters.clone();
+        let mut arguments = HashMap::new();
+        for parameter in parameters {
+            arguments.insert(parameter, self.next()?.text);
+        }
+        let body: Vec<Token> = self.macros[&name.text]
+            .body
+            .iter()
+            .map(|token| match arguments.get(&token.text) {
+                Some(argument) => Token {
+                    text: argument.clone(),
+                    ..token.clone()
+                },
+                None => token.clone(),
+            })
+            .collect();
+        for token in body.into_iter().rev() {
+            self.tokens.push_front(token);
+        }
+        Ok(())
+    }
+
+    /// The tokens between `{` and the matching `}`.
+    fn braces(&mut self) -> Result<Vec<Token>, AsmError> {
+        self.expect("{")?;
+        let mut depth = 1;
+        let mut tokens = Vec::new();
+        loop {
+            let token = self.next()?;
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Ok(tokens);
+                    }
+                }
+                _ => (),
+            }
+            tokens.push(token);
+        }
+    }
+
+    fn name(&mut self) -> Result<Token, AsmError> {
+        let token = self.next()?;
+        // Aliases can be redefined, only the real register names are taken.
+        let reserved = register_number(&token.text).is_some()
+            || parse_number(&token.text).is_some()
+            || token.text.starts_with(':')
+            || matches!(token.text.as_str(), "{" | "}" | ";");
+        if reserved {
+            return Err(token.error(format!("{} can't be used as a name", token.text)));
+        }
+        Ok(token)
+    }
+
+    fn define_label(&mut self, name: &Token, address: u16) -> Result<(), AsmError> {
+        if self.labels.contains_key(&name.text) || self.constants.contains_key(&name.text) {
+            return Err(name.error(format!("{} is defined twice", name.text)));
+        }
+        self.labels.insert(name.text.clone(), address);
+        Ok(())
+    }
+
+    fn define_constant(&mut self, name: &Token, value: f64) -> Result<(), AsmError> {
+        if self.labels.contains_key(&name.text) {
+            return Err(name.error(format!("{} is already a label", name.text)));
+        }
+        self.constants.insert(name.text.clone(), value);
+        Ok(())
+    }
+
+    fn register_name(&self, text: &str) -> Option<u8> {
+        match self.aliases.get(text) {
+            Some(&register) => Some(register),
+            None => register_number(text),
+        }
+    }
+
+    fn register(&mut self) -> Result<u8, AsmError> {
+        let token = self.next()?;
+        self.register_name(&token.text)
+            .ok_or_else(|| token.error(format!("Expected a register but found {}", token.text)))
+    }
+
+    /// Numbers, constants and labels defined so far.
+    fn literal(&self, text: &str) -> Option<i64> {
+        if let Some(number) = parse_number(text) {
+            return Some(number);
+        }
+        if let Some(&value) = self.constants.get(text) {
+            return Some(value as i64);
+        }
+        self.labels.get(text).map(|&address| address as i64)
+    }
+
+    /// A number, name or `{ calculation }`, names may be defined later.
+    fn value(&mut self) -> Result<Value, AsmError> {
+        if self.peek() == Some("{") {
+            let start = self.tokens[0].clone();
+            let tokens = self.braces()?;
+            return Ok(Value::Known(self.calculate(&start, &tokens)? as i64));
+        }
+        let token = self.next()?;
+        if let Some(value) = self.literal(&token.text) {
+            return Ok(Value::Known(value));
+        }
+        if self.register_name(&token.text).is_some() || token.text.starts_with(':') {
+            return Err(token.error(format!("Expected a value but found {}", token.text)));
+        }
+        Ok(Value::Forward(token))
+    }
+
+    /// A value that has to be known already.
+    fn number(&mut self) -> Result<i64, AsmError> {
+        match self.value()? {
+            Value::Known(value) => Ok(value),
+            Value::Forward(token) => Err(token.error(format!("Undefined name {}", token.text))),
+        }
+    }
+
+    fn byte(&mut self, token: &Token) -> Result<u8, AsmError> {
+        let value = self.number()?;
+        Ok(self.ranged(token, value, -0x80, 0xFF)? as u8)
+    }
+
+    fn ranged(&self, token: &Token, value: i64, min: i64, max: i64) -> Result<i64, AsmError> {
+        if value < min || value > max {
+            return Err(token.error(format!(
+                "{} is out of range {}..={} for {}",
+                value, min, max, token.text
+            )));
+        }
+        Ok(value)
+    }
+
+    fn register_opcode(&mut self, opcode: u16) -> Result<(), AsmError> {
+        let x = self.register()? as u16;
+        self.emit_opcode(opcode | x << 8)?;
+        Ok(())
+    }
+
+    /// Emits `opcode` with a 12 bit address operand.
+    fn address_opcode(&mut self, opcode: u16) -> Result<(), AsmError> {
+        let token = self.tokens.front().cloned().unwrap_or(self.last.clone());
+        self.emit_opcode(opcode)?;
+        self.fill_address(&token, (self.here - 2) as u16, FixupKind::Address)
+    }
+
+    /// Reads an address and writes it into the instruction at `address` now
+    /// or, for labels defined later, at the end.
+    fn fill_address(
+        &mut self,
+        token: &Token,
+        address: u16,
+        kind: FixupKind,
+    ) -> Result<(), AsmError> {
+        match self.value()? {
+            Value::Known(target) => self.patch(token, address, target, kind),
+            Value::Forward(token) => {
+                self.fixups.push(Fixup {
+                    token,
+                    address,
+                    kind,
+                });
+                Ok(())
+            }
+        }
+    }
+
+    fn patch(
+        &mut self,
+        token: &Token,
+        address: u16,
+        target: i64,
+        kind: FixupKind,
+    ) -> Result<(), AsmError> {
+        let index = (address - PROGRAM_START) as usize;
+        match kind {
+            FixupKind::Address => {
+                let target = self.ranged(token, target, 0, 0xFFF)?;
+                self.rom[index] |= (target >> 8) as u8;
+                self.rom[index + 1] = target as u8;
+            }
+            FixupKind::Long => {
+                let target = self.ranged(token, target, 0, 0xFFFF)?;
+                self.rom[index] = (target >> 8) as u8;
+                self.rom[index + 1] = target as u8;
+            }
+            FixupKind::UnpackHigh => self.rom[index + 1] |= (target >> 8) as u8 & 0xF,
+            FixupKind::UnpackLow => self.rom[index + 1] = target as u8,
+        }
+        Ok(())
+    }
+
+    fn emit_opcode(&mut self, opcode: u16) -> Result<(), AsmError> {
+        for byte in opcode.to_be_bytes() {
+            self.emit(byte)?;
+        }
+        Ok(())
+    }
+
+    fn emit_byte(&mut self, token: &Token, value: i64) -> Result<(), AsmError> {
+        let value = self.ranged(token, value, -0x80, 0xFF)?;
+        self.emit(value as u8)?;
+        Ok(())
+    }
+
+    fn emit(&mut self, byte: u8) -> Result<(), AsmError> {
+        let index = self.address(&self.last)? - PROGRAM_START;
+        let index = index as usize;
+        if index >= self.rom.len() {
+            self.rom.resize(index + 1, 0);
+        }
+        self.rom[index] = byte;
+        self.here += 1;
+        Ok(())
+    }
+
+    /// `here` as an address, an error once the program filled all 64 KiB.
+    fn address(&self, token: &Token) -> Result<u16, AsmError> {
+        u16::try_from(self.here).map_err(|_| token.error("Program runs past 0xFFFF"))
+    }
+
+    /// Evaluates a `:calc` expression. Like Octo, operators have no
+    /// precedence and are applied right to left, use parentheses to group.
+    fn calculate(&self, start: &Token, tokens: &[Token]) -> Result<f64, AsmError> {
+        let mut position = 0;
+        let value = self.expression(start, tokens, &mut position)?;
+        match tokens.get(position) {
+            Some(token) => Err(token.error(format!("Unexpected {}", token.text))),
+            None => Ok(value),
+        }
+    }
+
+    fn expression(
+        &self,
+        start: &Token,
+        tokens: &[Token],
+        position: &mut usize,
+    ) -> Result<f64, AsmError> {
+        let left = self.term(start, tokens, position)?;
+        let Some(operator) = tokens.get(*position) else {
+            return Ok(left);
+        };
+        if operator.text == ")" {
+            return Ok(left);
+        }
+        *position += 1;
+        let right = self.expression(start, tokens, position)?;
+        let (left_bits, right_bits) = (left as i64, right as i64);
+        let value = match operator.text.as_str() {
+            "+" => left + right,
+            "-" => left - right,
+            "*" => left * right,
+            "/" => left / right,
+            "%" => left % right,
+            "pow" => left.powf(right),
+            "min" => left.min(right),
+            "max" => left.max(right),
+            "&" => (left_bits & right_bits) as f64,
+            "|" => (left_bits | right_bits) as f64,
+            "^" => (left_bits ^ right_bits) as f64,
+            "<<" | ">>" => {
+                let shifted = u32::try_from(right_bits).ok().and_then(|amount| {
+                    match operator.text.as_str() {
+                        "<<" => left_bits.checked_shl(amount),
+                        _ => left_bits.checked_shr(amount),
+                    }
+                });
+                match shifted {
+                    Some(value) => value as f64,
+                    None => {
+                        return Err(operator.error(format!("Can't shift by {} bits", right_bits)))
+                    }
+                }
+            }
+            "<" => (left < right) as u8 as f64,
+            ">" => (left > right) as u8 as f64,
+            "<=" => (left <= right) as u8 as f64,
+            ">=" => (left >= right) as u8 as f64,
+            "==" => (left == right) as u8 as f64,
+            "!=" => (left != right) as u8 as f64,
+            _ => return Err(operator.error(format!("Unknown operator {}", operator.text))),
+        };
+        Ok(value)
+    }
+
+    fn term(&self, start: &Token, tokens: &[Token], position: &mut usize) -> Result<f64, AsmError> {
+        let Some(token) = tokens.get(*position) else {
+            return Err(start.error("Expected a value in calculation"));
+        };
+        *position += 1;
+        let unary = |function: fn(f64) -> f64, position: &mut usize| {
+            self.term(start, tokens, position).map(function)
+        };
+        match token.text.as_str() {
+            "(" => {
+                let value = self.expression(start, tokens, position)?;
+                match tokens.get(*position) {
+                    Some(close) if close.text == ")" => {
+                        *position += 1;
+                        Ok(value)
+                    }
+                    _ => Err(token.error("( is never closed")),
+                }
+            }
+            "-" => unary(|value| -value, position),
+            "~" => unary(|value| !(value as i64) as f64, position),
+            "!" => unary(|value| (value == 0.0) as u8 as f64, position),
+            "sin" => unary(f64::sin, position),
+            "cos" => unary(f64::cos, position),
+            "tan" => unary(f64::tan, position),
+            "exp" => unary(f64::exp, position),
+            "log" => unary(f64::ln, position),
+            "abs" => unary(f64::abs, position),
+            "sqrt" => unary(f64::sqrt, position),
+            "sign" => unary(f64::signum, position),
+            "ceil" => unary(f64::ceil, position),
+            "floor" => unary(f64::floor, position),
+            "@" => {
+                let address = self.term(start, tokens, position)? as i64;
+                let index = address - PROGRAM_START as i64;
+                Ok(self
+                    .rom
+                    .get(index as usize)
+                    .map_or(0.0, |&byte| byte as f64))
+            }
+            "HERE" => Ok(self.here as f64),
+            "PI" => Ok(PI),
+            "E" => Ok(E),
+            text => match self.constants.get(text) {
+                Some(&value) => Ok(value),
+                None => self
+                    .literal(text)
+                    .map(|value| value as f64)
+                    .ok_or_else(|| token.error(format!("Unknown name {}", text))),
+            },
+        }
+    }
+}
+
+/// `v0` to `vf`, in either case.
+fn register_number(text: &str) -> Option<u8> {
+    let index = text.strip_prefix(['v', 'V'])?;
+    if index.len() != 1 {
+        return None;
+    }
+    u8::from_str_radix(index, 16).ok()
+}
+
+fn parse_number(text: &str) -> Option<i64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let number = if let Some(hex) = digits.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(binary) = digits.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2).ok()?
+    } else if digits.starts_with(|c: char| c.is_ascii_digit()) {
+        digits.parse().ok()?
+    } else {
+        return None;
+    };
+    Some(if negative { -number } else { number })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rom(source: &str) -> Vec<u8> {
+        compile(source).unwrap().rom
+    }
+
+    #[test]
+    fn comparisons_go_through_vf() {
+        // VF := v1, VF =- v0, skip unless VF == 0.
+        assert_eq!(
+            rom("if v0 < v1 then v2 := 1"),
+            [0x8F, 0x10, 0x8F, 0x07, 0x4F, 0x00, 0x62, 0x01]
+        );
+    }
+
+    #[test]
+    fn while_jumps_past_again() {
+        assert_eq!(
+            rom("loop v0 += 1 while v0 != 5 again"),
+            [0x70, 0x01, 0x40, 0x05, 0x12, 0x08, 0x12, 0x00]
+        );
+    }
+
+    #[test]
+    fn forward_jumps_are_patched() {
+        assert_eq!(
+            rom("jump end v0 := 1 : end v1 := 2"),
+            [0x12, 0x04, 0x60, 0x01, 0x61, 0x02]
+        );
+    }
+
+    #[test]
+    fn unpack_splits_an_address() {
+        assert_eq!(
+            rom(":unpack 0xA data : data 0x12"),
+            [0x60, 0xA2, 0x61, 0x04, 0x12]
+        );
+        assert_eq!(
+            rom(": data 0x12 :unpack 0xA data"),
+            [0x12, 0x60, 0xA2, 0x61, 0x00]
+        );
+    }
+
+    #[test]
+    fn macro_arguments_are_substituted() {
+        assert_eq!(
+            rom(":macro set reg value { reg := value } set v3 7 set va 0x20"),
+            [0x63, 0x07, 0x6A, 0x20]
+        );
+    }
+
+    #[test]
+    fn code_ends_at_0xffff() {
+        assert_eq!(rom(":org 0xFFFE clear").len(), 0x10000 - 0x200);
+        let error = compile(":org 0xFFFE clear v0 := 1").unwrap_err();
+        assert_eq!(error.message, "Program runs past 0xFFFF");
+        assert!(compile(":org 0xFFFF clear").is_err());
+    }
+
+    #[test]
+    fn calc_evaluates_right_to_left() {
+        assert_eq!(rom(":calc x { 2 * 3 + 4 } v0 := x"), [0x60, 14]);
+        assert_eq!(rom(":calc x { 10 - 4 - 3 } v0 := x"), [0x60, 9]);
+        assert_eq!(rom(":calc x { ( 2 * 3 ) + 4 } v0 := x"), [0x60, 10]);
+        assert_eq!(rom(":calc x { 1 << 3 + 1 } v0 := x"), [0x60, 16]);
+    }
+
+    #[test]
+    fn calc_rejects_out_of_range_shifts() {
+        for source in [
+            ":calc x { 1 << 70 }",
+            ":calc x { 8 >> 64 }",
+            ":calc x { 1 << -1 }",
+        ] {
+            let error = compile(source).unwrap_err();
+            assert!(error.message.starts_with("Can't shift"), "{}", source);
+        }
+    }
+}
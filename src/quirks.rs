@@ -0,0 +1,219 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Opcode behaviors that differ between CHIP-8 interpreters.
+///
+/// `Quirks::default()` is the behavior this emulator always had. The named
+/// presets follow the interpreters ROMs are usually written for.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    /// `8XY6`/`8XYE` shift VX in place instead of shifting VY into VX.
+    pub shift: bool,
+    /// `FX55`/`FX65` leave `I` pointing past the last register they touched.
+    pub memory_increment: bool,
+    /// `BNNN` jumps to `NNN + VX` (with X the top nibble of NNN) instead of `NNN + V0`.
+    pub jump_with_vx: bool,
+    /// `8XY1`, `8XY2` and `8XY3` set VF to 0.
+    pub vf_reset: bool,
+    /// `DXYN` waits for the next timer tick, so at most one sprite is drawn per frame.
+    pub display_wait: bool,
+    /// Sprites are cut off at the screen edges instead of wrapping around.
+    pub clipping: bool,
+}
+
+impl Default for Quirks {
+    fn default() -> Quirks {
+        Quirks {
+            shift: false,
+            memory_increment: false,
+            jump_with_vx: false,
+            vf_reset: false,
+            display_wait: false,
+            clipping: true,
+        }
+    }
+}
+
+impl Quirks {
+    pub fn cosmac_vip() -> Quirks {
+        Quirks {
+            shift: false,
+            memory_increment: true,
+            jump_with_vx: false,
+            vf_reset: true,
+            display_wait: true,
+            clipping: true,
+        }
+    }
+
+    pub fn chip48() -> Quirks {
+        Quirks {
+            shift: true,
+            memory_increment: false,
+            jump_with_vx: true,
+            vf_reset: false,
+            display_wait: false,
+            clipping: true,
+        }
+    }
+
+    pub fn superchip() -> Quirks {
+        Quirks {
+            shift: true,
+            memory_increment: false,
+            jump_with_vx: true,
+            vf_reset: false,
+            display_wait: false,
+            clipping: true,
+        }
+    }
+
+    pub fn xochip() -> Quirks {
+        Quirks {
+            shift: false,
+            memory_increment: true,
+            jump_with_vx: false,
+            vf_reset: false,
+            display_wait: false,
+            clipping: false,
+        }
+    }
+
+    /// Looks up a preset by name, e.g. for command line options.
+    pub fn preset(name: &str) -> Option<Quirks> {
+        match name.to_ascii_lowercase().as_str() {
+            "default" => Some(Quirks::default()),
+            "vip" | "cosmac-vip" | "chip-8" => Some(Quirks::cosmac_vip()),
+            "chip-48" | "chip48" => Some(Quirks::chip48()),
+            "schip" | "superchip" | "super-chip" => Some(Quirks::superchip()),
+            "xo-chip" | "xochip" => Some(Quirks::xochip()),
+            _ => None,
+        }
+    }
+
+    /// Parses a comma separated list of an optional preset followed by quirk
+    /// names to turn on, or off with a `no-` prefix, e.g. `vip,shift,no-clipping`.
+    pub fn parse(spec: &str) -> Result<Quirks, String> {
+        let mut quirks = Quirks::default();
+        for (index, item) in spec.split(',').map(str::trim).enumerate() {
+            if index == 0 {
+                if let Some(preset) = Quirks::preset(item) {
+                    quirks = preset;
+                    continue;
+                }
+            }
+            let (name, enabled) = match item.strip_prefix("no-") {
+                Some(name) => (name, false),
+                None => (item, true),
+            };
+            let quirk = match name.replace('_', "-").as_str() {
+                "shift" => &mut quirks.shift,
+                "memory-increment" => &mut quirks.memory_increment,
+                "jump-with-vx" => &mut quirks.jump_with_vx,
+                "vf-reset" => &mut quirks.vf_reset,
+                "display-wait" => &mut quirks.display_wait,
+                "clipping" => &mut quirks.clipping,
+                _ => return Err(format!("Unknown quirk or preset {}", item)),
+            };
+            *quirk = enabled;
+        }
+        Ok(quirks)
+    }
+
+    fn flags(&self) -> [(&'static str, bool); 6] {
+        [
+            ("shift", self.shift),
+            ("memory-increment", self.memory_increment),
+            ("jump-with-vx", self.jump_with_vx),
+            ("vf-reset", self.vf_reset),
+            ("display-wait", self.display_wait),
+            ("clipping", self.clipping),
+        ]
+    }
+}
+
+/// Lists every quirk in the form `Quirks::parse` reads.
+impl fmt::Display for Quirks {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let flags: Vec<String> = self
+            .flags()
+            .iter()
+            .map(|&(name, enabled)| {
+                if enabled {
+                    name.to_string()
+                } else {
+                    format!("no-{}", name)
+                }
+            })
+            .collect();
+        write!(f, "{}", flags.join(","))
+    }
+}
+
+impl FromStr for Quirks {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Quirks, String> {
+        Quirks::parse(spec)
+    }
+}
+
+/// The instruction set a program is written for. Each variant also accepts
+/// all instructions of the ones before it.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Variant {
+    #[default]
+    Chip8,
+    SuperChip,
+    XoChip,
+}
+
+impl Variant {
+    pub fn from_name(name: &str) -> Option<Variant> {
+        match name.to_ascii_lowercase().as_str() {
+            "chip-8" | "chip8" => Some(Variant::Chip8),
+            "schip" | "superchip" | "super-chip" => Some(Variant::SuperChip),
+            "xo-chip" | "xochip" => Some(Variant::XoChip),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Variant::Chip8 => "chip-8",
+            Variant::SuperChip => "schip",
+            Variant::XoChip => "xo-chip",
+        }
+    }
+
+    pub fn has_superchip_instructions(self) -> bool {
+        self >= Variant::SuperChip
+    }
+
+    pub fn has_xochip_instructions(self) -> bool {
+        self >= Variant::XoChip
+    }
+
+    /// Bytes of RAM, XO-CHIP extends the 4 KiB to the full 16 bit address space.
+    pub fn memory_size(self) -> usize {
+        if self.has_xochip_instructions() {
+            0x10000
+        } else {
+            0x1000
+        }
+    }
+}
+
+impl fmt::Display for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Variant {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Variant, String> {
+        Variant::from_name(name).ok_or_else(|| format!("Unknown variant {}", name))
+    }
+}
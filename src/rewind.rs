@@ -0,0 +1,220 @@
+use crate::{Chip8Cpu, Interpreter, Rng, Screen};
+use std::collections::VecDeque;
+use std::mem::size_of;
+use std::rc::Rc;
+
+/// The last frames of the machine, to step back through them one by one.
+/// RAM is kept as the bytes that changed from one frame to the next and
+/// frames that didn't draw share the screen of the frame before, so a
+/// snapshot is cheap to take every frame.
+pub struct RewindBuffer {
+    /// Oldest first, the newest is the current frame.
+    snapshots: VecDeque<Snapshot>,
+    /// RAM as of the newest snapshot.
+    ram: Vec<u8>,
+    max_frames: usize,
+    max_bytes: usize,
+    /// Roughly the memory the snapshots take up, not counting `ram`.
+    bytes: usize,
+}
+
+struct Snapshot {
+    cpu: Chip8Cpu,
+    screen: Rc<Screen>,
+    /// Addresses and bytes that turn the RAM of the next newer snapshot into
+    /// the RAM of this one.
+    undo: Vec<(u16, u8)>,
+    rpl_flags: [u8; 16],
+    audio_pattern: [u8; 16],
+    pitch: u8,
+    vblank: bool,
+    exited: bool,
+    rng: Rng,
+    /// What this snapshot counts against `max_bytes`.
+    bytes: usize,
+}
+
+impl RewindBuffer {
+    /// Keeps up to `max_frames` frames, fewer if they would take more than
+    /// `max_bytes`.
+    pub fn new(max_frames: usize, max_bytes: usize) -> RewindBuffer {
+        RewindBuffer {
+            snapshots: VecDeque::new(),
+            ram: Vec::new(),
+            max_frames,
+            max_bytes,
+            bytes: 0,
+        }
+    }
+
+    /// How many frames the machine can go back.
+    pub fn depth(&self) -> usize {
+        self.snapshots.len().saturating_sub(1)
+    }
+
+    /// Remembers the state the machine is in, called after every frame.
+    pub fn capture(&mut self, interpreter: &Interpreter) {
+        if self.max_frames == 0 {
+            return;
+        }
+        if self.ram.len() != interpreter.ram.len() {
+            self.snapshots.clear();
+            self.bytes = 0;
+            self.ram = interpreter.ram.clone();
+        }
+        if let Some(newest) = self.snapshots.back_mut() {
+            newest.undo = self
+                .ram
+                .iter()
+                .zip(&interpreter.ram)
+                .enumerate()
+                .filter(|(_, (old, new))| old != new)
+                .map(|(address, (&old, _))| (address as u16, old))
+                .collect();
+            let undo_bytes = newest.undo.len() * size_of::<(u16, u8)>();
+            newest.bytes += undo_bytes;
+            self.bytes += undo_bytes;
+            self.ram.copy_from_slice(&interpreter.ram);
+        }
+
+        let mut bytes = size_of::<Snapshot>() + interpreter.cpu.stack.len() * size_of::<u16>();
+        let screen = match self.snapshots.back() {
+            Some(newest) if *newest.screen == interpreter.screen => Rc::clone(&newest.screen),
+            _ => {
+                bytes += interpreter.screen.pixels().len();
+                Rc::new(interpreter.screen.clone())
+            }
+        };
+        self.snapshots.push_back(Snapshot {
+            cpu: interpreter.cpu.clone(),
+            screen,
+            undo: Vec::new(),
+            rpl_flags: interpreter.rpl_flags,
+            audio_pattern: interpreter.audio_pattern,
+            pitch: interpreter.pitch,
+            vblank: interpreter.vblank,
+            exited: interpreter.exited,
+            rng: interpreter.rng,
+            bytes,
+        });
+        self.bytes += bytes;
+
+        while self.snapshots.len() > 1
+            && (self.snapshots.len() > self.max_frames || self.bytes > self.max_bytes)
+        {
+            if let Some(oldest) = self.snapshots.pop_front() {
+                self.bytes -= oldest.bytes;
+            }
+        }
+    }
+
+    /// Puts the machine back to the frame before the newest snapshot and
+    /// forgets the newest. False if there is no older frame left. The held
+    /// keys are left alone.
+    pub fn step_back(&mut self, interpreter: &mut Interpreter) -> bool {
+        if self.snapshots.len() < 2 || self.ram.len() != interpreter.ram.len() {
+            return false;
+        }
+        if let Some(newest) = self.snapshots.pop_back() {
+            self.bytes -= newest.bytes;
+        }
+        let Some(snapshot) = self.snapshots.back_mut() else {
+            return false;
+        };
+        for &(address, byte) in &snapshot.undo {
+            self.ram[address as usize] = byte;
+        }
+        let undo_bytes = snapshot.undo.len() * size_of::<(u16, u8)>();
+        snapshot.bytes -= undo_bytes;
+        self.bytes -= undo_bytes;
+        snapshot.undo = Vec::new();
+
+        // Also undoes what ran since the newest snapshot was taken.
+        for address in 0..self.ram.len() {
+            if interpreter.ram[address] != self.ram[address] {
+                crate::write_ram(interpreter, address, &[self.ram[address]]);
+            }
+        }
+        interpreter.cpu = snapshot.cpu.clone();
+        interpreter.screen = Screen::clone(&snapshot.screen);
+        interpreter.rpl_flags = snapshot.rpl_flags;
+        interpreter.audio_pattern = snapshot.audio_pattern;
+        interpreter.pitch = snapshot.pitch;
+        interpreter.vblank = snapshot.vblank;
+        interpreter.exited = snapshot.exited;
+        interpreter.rng = snapshot.rng;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::savestate::save_state;
+    use crate::{load_rom, run_frame, setup_emulator, Quirks, RngMode, Variant};
+
+    /// Counts up in V0 and stores it at 0x300 every instruction, so every
+    /// frame writes RAM.
+    fn counter() -> Interpreter {
+        let mut interpreter = setup_emulator(Variant::SuperChip, Quirks::default());
+        load_rom(
+            &mut interpreter,
+            &[0xA3, 0x00, 0x70, 0x01, 0xF0, 0x55, 0x12, 0x02],
+        );
+        interpreter.rng = Rng::new(RngMode::Xorshift, 1);
+        interpreter
+    }
+
+    /// Runs `frames` frames, capturing and saving the state after each.
+    fn run(
+        interpreter: &mut Interpreter,
+        rewind: &mut RewindBuffer,
+        frames: usize,
+    ) -> Vec<Vec<u8>> {
+        (0..frames)
+            .map(|_| {
+                run_frame(interpreter, 5).unwrap();
+                rewind.capture(interpreter);
+                save_state(interpreter)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn stepping_back_restores_earlier_frames_exactly() {
+        let mut interpreter = counter();
+        let mut rewind = RewindBuffer::new(100, usize::MAX);
+        let states = run(&mut interpreter, &mut rewind, 10);
+        // Half a frame the buffer hasn't seen is undone as well.
+        crate::emulate(&mut interpreter).unwrap();
+        assert!(rewind.step_back(&mut interpreter));
+        assert_eq!(save_state(&interpreter), states[8]);
+        for _ in 0..3 {
+            assert!(rewind.step_back(&mut interpreter));
+        }
+        assert_eq!(save_state(&interpreter), states[5]);
+        // Running on from there gives the same frames again.
+        let again = run(&mut interpreter, &mut rewind, 4);
+        assert_eq!(again, states[6..10]);
+    }
+
+    #[test]
+    fn old_frames_are_dropped_over_budget() {
+        let mut interpreter = counter();
+        let mut unlimited = RewindBuffer::new(100, usize::MAX);
+        run(&mut interpreter, &mut unlimited, 4);
+        let budget = unlimited.bytes;
+
+        let mut interpreter = counter();
+        let mut rewind = RewindBuffer::new(100, budget);
+        let states = run(&mut interpreter, &mut rewind, 20);
+        assert!(rewind.bytes <= budget);
+        let depth = rewind.depth();
+        assert!((1..19).contains(&depth), "depth {}", depth);
+        for _ in 0..depth {
+            assert!(rewind.step_back(&mut interpreter));
+        }
+        assert!(!rewind.step_back(&mut interpreter));
+        assert_eq!(save_state(&interpreter), states[19 - depth]);
+    }
+}
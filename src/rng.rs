@@ -0,0 +1,192 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Where the random numbers of `CXNN` come from. The whole state is in here,
+/// so it can be saved and restored with the rest of the machine.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Rng {
+    /// xorshift64*, never zero.
+    Xorshift(u64),
+    /// Steps a counter and adds the byte of `TABLE` it points at to the last
+    /// result, a cheap generator in the style of 8-bit interpreters. The last
+    /// result is the high byte and the counter the low byte.
+    Table(u16),
+}
+
+/// What `Rng::Table` adds up, a fixed page of well mixed bytes.
+const TABLE: [u8; 256] = table();
+
+const fn table() -> [u8; 256] {
+    let mut page = [0; 256];
+    let mut state: u32 = 0x1802;
+    let mut index = 0;
+    while index < page.len() {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        page[index] = (state >> 24) as u8;
+        index += 1;
+    }
+    page
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum RngMode {
+    #[default]
+    Xorshift,
+    Table,
+}
+
+impl RngMode {
+    pub fn from_name(name: &str) -> Option<RngMode> {
+        match name.to_ascii_lowercase().as_str() {
+            "xorshift" => Some(RngMode::Xorshift),
+            "table" => Some(RngMode::Table),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            RngMode::Xorshift => "xorshift",
+            RngMode::Table => "table",
+        }
+    }
+}
+
+impl fmt::Display for RngMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for RngMode {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<RngMode, String> {
+        RngMode::from_name(name).ok_or_else(|| format!("Unknown random number generator {}", name))
+    }
+}
+
+impl Rng {
+    /// The same seed gives the same numbers on every run.
+    pub fn new(mode: RngMode, seed: u64) -> Rng {
+        match mode {
+            RngMode::Xorshift => {
+                // splitmix64, so similar seeds don't start out similar.
+                let mut state = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+                state = (state ^ (state >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+                state = (state ^ (state >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+                state ^= state >> 31;
+                Rng::Xorshift(if state == 0 { 1 } else { state })
+            }
+            RngMode::Table => Rng::Table(seed as u16),
+        }
+    }
+
+    /// Seeded from the clock, for different numbers on every run.
+    pub fn from_time(mode: RngMode) -> Rng {
+        Rng::new(mode, Rng::time_seed())
+    }
+
+    /// A seed from the clock, for runs that need to know theirs.
+    pub fn time_seed() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_nanos() as u64)
+    }
+
+    pub fn mode(&self) -> RngMode {
+        match self {
+            Rng::Xorshift(_) => RngMode::Xorshift,
+            Rng::Table(_) => RngMode::Table,
+        }
+    }
+
+    pub(crate) fn next_byte(&mut self) -> u8 {
+        match self {
+            Rng::Xorshift(state) => {
+                *state ^= *state >> 12;
+                *state ^= *state << 25;
+                *state ^= *state >> 27;
+                (state.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 56) as u8
+            }
+            Rng::Table(state) => {
+                let counter = (*state as u8).wrapping_add(1);
+                let code = TABLE[counter as usize];
+                let result = ((*state >> 8) as u8).wrapping_add(code);
+                *state = u16::from_be_bytes([result, counter]);
+                result
+            }
+        }
+    }
+
+    /// A mode byte and the state, big endian.
+    pub(crate) fn to_bytes(self) -> [u8; 9] {
+        let (mode, state) = match self {
+            Rng::Xorshift(state) => (0, state),
+            Rng::Table(state) => (1, state as u64),
+        };
+        let mut bytes = [mode; 9];
+        bytes[1..].copy_from_slice(&state.to_be_bytes());
+        bytes
+    }
+
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Option<Rng> {
+        let state = u64::from_be_bytes(bytes.get(1..9)?.try_into().ok()?);
+        match bytes[0] {
+            0 if state != 0 => Some(Rng::Xorshift(state)),
+            1 => Some(Rng::Table(u16::try_from(state).ok()?)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stream(mut rng: Rng, len: usize) -> Vec<u8> {
+        (0..len).map(|_| rng.next_byte()).collect()
+    }
+
+    #[test]
+    fn table_mode_adds_the_byte_after_the_counter() {
+        let mut rng = Rng::Table(u16::from_be_bytes([0x40, 0xFF]));
+        assert_eq!(rng.next_byte(), 0x40u8.wrapping_add(TABLE[0]));
+        assert_eq!(
+            rng.next_byte(),
+            0x40u8.wrapping_add(TABLE[0]).wrapping_add(TABLE[1])
+        );
+    }
+
+    #[test]
+    fn table_mode_does_not_stall() {
+        let numbers = stream(Rng::new(RngMode::Table, 7), 512);
+        let repeats = numbers.windows(2).filter(|pair| pair[0] == pair[1]).count();
+        assert!(repeats < 16, "{} repeats", repeats);
+        let mut seen = numbers.clone();
+        seen.sort_unstable();
+        seen.dedup();
+        assert!(seen.len() > 128, "only {} different numbers", seen.len());
+    }
+
+    #[test]
+    fn same_seed_same_numbers() {
+        for mode in [RngMode::Xorshift, RngMode::Table] {
+            assert_eq!(
+                stream(Rng::new(mode, 42), 64),
+                stream(Rng::new(mode, 42), 64)
+            );
+        }
+    }
+
+    #[test]
+    fn state_survives_bytes() {
+        for mode in [RngMode::Xorshift, RngMode::Table] {
+            let rng = Rng::new(mode, 99);
+            assert_eq!(Rng::from_bytes(&rng.to_bytes()), Some(rng));
+        }
+    }
+}
@@ -0,0 +1,261 @@
+use crate::frontend::Palette;
+use crate::{Quirks, Variant};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+const PROGRAMS: &str = include_str!("../data/programs.json");
+const PLATFORMS: &str = include_str!("../data/platforms.json");
+
+/// A program of the database, in the shape of `programs.json` of the
+/// chip-8-database project. Fields not used here are ignored.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Program {
+    pub title: String,
+    #[serde(default)]
+    pub authors: Vec<String>,
+    /// The releases of the program by SHA-1.
+    pub roms: BTreeMap<String, Rom>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Rom {
+    /// Platform ids, the preferred one first.
+    pub platforms: Vec<String>,
+    pub tickrate: Option<u32>,
+    /// CHIP-8 keys by what they do in the game, e.g. `"up": 5`.
+    #[serde(default)]
+    pub keys: BTreeMap<String, u8>,
+    pub colors: Option<Colors>,
+    /// Quirks that differ from those of the platform, by platform id.
+    #[serde(default)]
+    pub quirky_platforms: BTreeMap<String, QuirkOverrides>,
+}
+
+/// Quirks of a platform a ROM needs set differently, unset ones are kept.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QuirkOverrides {
+    pub shift: Option<bool>,
+    pub memory_increment_by_x: Option<bool>,
+    pub memory_leave_i_unchanged: Option<bool>,
+    pub wrap: Option<bool>,
+    pub jump: Option<bool>,
+    pub vblank: Option<bool>,
+    pub logic: Option<bool>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Colors {
+    /// Hex colors by the planes a pixel is set on.
+    pub pixels: Option<Vec<String>>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Platform {
+    id: String,
+    default_tickrate: u32,
+    quirks: PlatformQuirks,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PlatformQuirks {
+    shift: bool,
+    memory_increment_by_x: bool,
+    memory_leave_i_unchanged: bool,
+    wrap: bool,
+    jump: bool,
+    vblank: bool,
+    logic: bool,
+}
+
+impl PlatformQuirks {
+    fn with(self, overrides: &QuirkOverrides) -> PlatformQuirks {
+        PlatformQuirks {
+            shift: overrides.shift.unwrap_or(self.shift),
+            memory_increment_by_x: overrides
+                .memory_increment_by_x
+                .unwrap_or(self.memory_increment_by_x),
+            memory_leave_i_unchanged: overrides
+                .memory_leave_i_unchanged
+                .unwrap_or(self.memory_leave_i_unchanged),
+            wrap: overrides.wrap.unwrap_or(self.wrap),
+            jump: overrides.jump.unwrap_or(self.jump),
+            vblank: overrides.vblank.unwrap_or(self.vblank),
+            logic: overrides.logic.unwrap_or(self.logic),
+        }
+    }
+
+    /// `FX55`/`FX65` only know two ways of treating `I`, incrementing by X
+    /// (SUPER-CHIP 1.0) is taken as leaving it unchanged.
+    fn quirks(&self) -> Quirks {
+        Quirks {
+            shift: self.shift,
+            memory_increment: !self.memory_increment_by_x && !self.memory_leave_i_unchanged,
+            jump_with_vx: self.jump,
+            vf_reset: self.logic,
+            display_wait: self.vblank,
+            clipping: !self.wrap,
+        }
+    }
+}
+
+/// What the database knows about a ROM, resolved to what the core needs.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    pub title: String,
+    pub authors: Vec<String>,
+    /// The id of the platform the settings are for, e.g. `originalChip8`.
+    pub platform: String,
+    pub variant: Variant,
+    pub quirks: Quirks,
+    /// Instructions per frame.
+    pub tickrate: u32,
+    pub keys: BTreeMap<String, u8>,
+    pub palette: Option<Palette>,
+}
+
+pub struct Database {
+    programs: Vec<Program>,
+    platforms: Vec<Platform>,
+}
+
+impl Database {
+    /// The database compiled into the emulator.
+    pub fn embedded() -> Database {
+        Database {
+            programs: serde_json::from_str(PROGRAMS).expect("embedded programs.json is valid"),
+            platforms: serde_json::from_str(PLATFORMS).expect("embedded platforms.json is valid"),
+        }
+    }
+
+    /// Adds programs from JSON in the shape of `programs.json`. They take
+    /// precedence over the ones already known, e.g. to describe one's own ROMs.
+    pub fn add_programs(&mut self, json: &str) -> Result<(), String> {
+        let programs: Vec<Program> = serde_json::from_str(json).map_err(|err| err.to_string())?;
+        self.programs.splice(0..0, programs);
+        Ok(())
+    }
+
+    /// Looks up a ROM by its SHA-1, see `sha1_hex`. ROMs only listed for
+    /// platforms this emulator doesn't have are not found.
+    pub fn lookup(&self, sha1: &str) -> Option<Entry> {
+        let sha1 = sha1.to_ascii_lowercase();
+        let (program, rom) = self.programs.iter().find_map(|program| {
+            program
+                .roms
+                .iter()
+                .find(|(hash, _)| hash.to_ascii_lowercase() == sha1)
+                .map(|(_, rom)| (program, rom))
+        })?;
+        let (platform, variant) = rom.platforms.iter().find_map(|id| {
+            let variant = platform_variant(id)?;
+            let platform = self.platforms.iter().find(|platform| platform.id == *id)?;
+            Some((platform, variant))
+        })?;
+        let palette = rom
+            .colors
+            .as_ref()
+            .and_then(|colors| colors.pixels.as_ref())
+            .and_then(|pixels| Palette::parse(&pixels.join(",")).ok());
+        let quirks = match rom.quirky_platforms.get(&platform.id) {
+            Some(overrides) => platform.quirks.with(overrides),
+            None => platform.quirks,
+        };
+        Some(Entry {
+            title: program.title.clone(),
+            authors: program.authors.clone(),
+            platform: platform.id.clone(),
+            variant,
+            quirks: quirks.quirks(),
+            tickrate: rom.tickrate.unwrap_or(platform.default_tickrate),
+            keys: rom.keys.clone(),
+            palette,
+        })
+    }
+}
+
+/// The instruction set of a platform of the database, if it is one this
+/// emulator has.
+fn platform_variant(id: &str) -> Option<Variant> {
+    match id {
+        "originalChip8" | "hybridVIP" | "modernChip8" | "chip48" => Some(Variant::Chip8),
+        "superchip1" | "superchip" => Some(Variant::SuperChip),
+        "xochip" => Some(Variant::XoChip),
+        _ => None,
+    }
+}
+
+/// The hash ROMs are keyed by, as lowercase hex.
+pub fn sha1_hex(rom: &[u8]) -> String {
+    sha1_smol::Sha1::from(rom).digest().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn embedded_database_knows_the_bundled_roms() {
+        let database = Database::embedded();
+        let pong = sha1_hex(include_bytes!("../games/pong.rom"));
+        let entry = database.lookup(&pong).unwrap();
+        assert_eq!(entry.title, "Pong");
+        assert_eq!(entry.variant, Variant::Chip8);
+    }
+
+    #[test]
+    fn embedded_platforms_cover_all_upstream_ids() {
+        let platforms: Vec<Platform> = serde_json::from_str(PLATFORMS).unwrap();
+        let upstream = [
+            "originalChip8",
+            "hybridVIP",
+            "modernChip8",
+            "chip8x",
+            "chip48",
+            "superchip1",
+            "superchip",
+            "megachip8",
+            "xochip",
+        ];
+        for id in upstream {
+            assert!(platforms.iter().any(|platform| platform.id == id), "{}", id);
+        }
+    }
+
+    #[test]
+    fn reads_entries_of_the_upstream_database() {
+        let mut database = Database::embedded();
+        let json = r##"[{
+            "title": "Test",
+            "description": "Fields not used here are ignored.",
+            "release": "2024",
+            "authors": ["Someone"],
+            "images": ["test.png"],
+            "roms": {
+                "00000000000000000000000000000000000000AA": {
+                    "file": "test.ch8",
+                    "platforms": ["megachip8", "superchip"],
+                    "quirkyPlatforms": { "superchip": { "shift": false, "jump": false } },
+                    "tickrate": 20,
+                    "startAddress": 512,
+                    "screenRotation": 0,
+                    "keys": { "a": 6 },
+                    "colors": { "pixels": ["#000000", "#ffffff"], "buzzer": "#990000" }
+                }
+            }
+        }]"##;
+        database.add_programs(json).unwrap();
+        let entry = database
+            .lookup("00000000000000000000000000000000000000aa")
+            .unwrap();
+        assert_eq!(entry.platform, "superchip");
+        assert_eq!(entry.variant, Variant::SuperChip);
+        assert_eq!(entry.tickrate, 20);
+        assert!(!entry.quirks.shift && !entry.quirks.jump_with_vx);
+        assert!(!entry.quirks.memory_increment);
+        assert!(entry.palette.is_some());
+    }
+}
@@ -0,0 +1,252 @@
+use crate::error::StateError;
+use crate::{Interpreter, Rng, Screen, Variant, STACK_SIZE};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const MAGIC: &[u8; 4] = b"C8ST";
+/// Version 2 added the random number generator.
+const VERSION: u8 = 2;
+
+/// Serializes the whole machine: CPU registers, stack, timers, RAM, screen,
+/// keypad, the SUPER-CHIP/XO-CHIP extra state and the random number
+/// generator. Multi byte values are big endian like in CHIP-8 memory.
+pub fn save_state(interpreter: &Interpreter) -> Vec<u8> {
+    let cpu = &interpreter.cpu;
+    let mut data = Vec::with_capacity(interpreter.ram.len() + 1024);
+    data.extend_from_slice(MAGIC);
+    data.push(VERSION);
+    data.push(variant_id(interpreter.variant));
+
+    data.extend_from_slice(&cpu.programm_counter.to_be_bytes());
+    data.extend_from_slice(&cpu.i.to_be_bytes());
+    data.push(cpu.delay_timer);
+    data.push(cpu.sound_timer);
+    data.extend_from_slice(&cpu.gen_purpose_registers);
+    data.push(cpu.stack.len() as u8);
+    for address in &cpu.stack {
+        data.extend_from_slice(&address.to_be_bytes());
+    }
+
+    data.extend_from_slice(&(interpreter.ram.len() as u32).to_be_bytes());
+    data.extend_from_slice(&interpreter.ram);
+
+    data.push(interpreter.screen.is_hires() as u8);
+    data.push(interpreter.screen.selected_planes());
+    data.extend_from_slice(interpreter.screen.pixels());
+
+    data.extend(interpreter.input.iter().map(|&pressed| pressed as u8));
+    data.extend_from_slice(&interpreter.rpl_flags);
+    data.extend_from_slice(&interpreter.audio_pattern);
+    data.push(interpreter.pitch);
+    data.push(interpreter.vblank as u8);
+    data.push(interpreter.exited as u8);
+    data.extend_from_slice(&interpreter.rng.to_bytes());
+    data
+}
+
+/// Restores a state written by `save_state`. The interpreter is only changed
+/// if the whole state could be read. Version 1 states keep the current random
+/// number generator.
+pub fn load_state(interpreter: &mut Interpreter, data: &[u8]) -> Result<(), StateError> {
+    let mut reader = Reader { data };
+    if reader.bytes(MAGIC.len()).ok() != Some(&MAGIC[..]) {
+        return Err(StateError::NotASaveState);
+    }
+    let version = reader.u8()?;
+    if version != 1 && version != VERSION {
+        return Err(StateError::UnsupportedVersion(version));
+    }
+    if reader.u8()? != variant_id(interpreter.variant) {
+        return Err(StateError::VariantMismatch);
+    }
+
+    let programm_counter = reader.u16()?;
+    let i = reader.u16()?;
+    let delay_timer = reader.u8()?;
+    let sound_timer = reader.u8()?;
+    let mut gen_purpose_registers = [0; 16];
+    gen_purpose_registers.copy_from_slice(reader.bytes(16)?);
+    let stack_len = reader.u8()? as usize;
+    if stack_len > STACK_SIZE {
+        return Err(StateError::Corrupted);
+    }
+    let stack = (0..stack_len)
+        .map(|_| reader.u16())
+        .collect::<Result<Vec<u16>, StateError>>()?;
+
+    let ram_len = reader.u32()? as usize;
+    if ram_len != interpreter.ram.len() {
+        return Err(StateError::VariantMismatch);
+    }
+    let ram = reader.bytes(ram_len)?.to_vec();
+
+    let hires = reader.u8()? != 0;
+    let planes = reader.u8()?;
+    let pixel_count = if hires {
+        crate::screen::HIRES_WIDTH * crate::screen::HIRES_HEIGHT
+    } else {
+        crate::screen::LORES_WIDTH * crate::screen::LORES_HEIGHT
+    };
+    let pixels = reader.bytes(pixel_count)?.to_vec();
+    let screen = Screen::from_parts(hires, planes, pixels).ok_or(StateError::Corrupted)?;
+
+    let mut input = [false; 16];
+    for (key, &pressed) in input.iter_mut().zip(reader.bytes(16)?) {
+        *key = pressed != 0;
+    }
+    let mut rpl_flags = [0; 16];
+    rpl_flags.copy_from_slice(reader.bytes(16)?);
+    let mut audio_pattern = [0; 16];
+    audio_pattern.copy_from_slice(reader.bytes(16)?);
+    let pitch = reader.u8()?;
+    let vblank = reader.u8()? != 0;
+    let exited = reader.u8()? != 0;
+    let rng = match version {
+        1 => interpreter.rng,
+        _ => Rng::from_bytes(reader.bytes(9)?).ok_or(StateError::Corrupted)?,
+    };
+    if !reader.data.is_empty() {
+        return Err(StateError::Corrupted);
+    }
+
+    interpreter.cpu.programm_counter = programm_counter;
+    interpreter.cpu.i = i;
+    interpreter.cpu.delay_timer = delay_timer;
+    interpreter.cpu.sound_timer = sound_timer;
+    interpreter.cpu.gen_purpose_registers = gen_purpose_registers;
+    interpreter.cpu.stack = stack;
+    interpreter.decoded = vec![None; ram.len()];
+    interpreter.ram = ram;
+    interpreter.screen = screen;
+    interpreter.input = input;
+    interpreter.rpl_flags = rpl_flags;
+    interpreter.audio_pattern = audio_pattern;
+    interpreter.pitch = pitch;
+    interpreter.vblank = vblank;
+    interpreter.exited = exited;
+    interpreter.rng = rng;
+    Ok(())
+}
+
+pub fn save_state_to_file(interpreter: &Interpreter, path: impl AsRef<Path>) -> io::Result<()> {
+    fs::write(path, save_state(interpreter))
+}
+
+pub fn load_state_from_file(
+    interpreter: &mut Interpreter,
+    path: impl AsRef<Path>,
+) -> io::Result<()> {
+    let data = fs::read(path)?;
+    load_state(interpreter, &data).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+fn variant_id(variant: Variant) -> u8 {
+    match variant {
+        Variant::Chip8 => 0,
+        Variant::SuperChip => 1,
+        Variant::XoChip => 2,
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+        if self.data.len() < len {
+            return Err(StateError::Truncated);
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, StateError> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, StateError> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{load_rom, run_frame, setup_emulator, Quirks, RngMode, PROGRAM_START};
+
+    /// A machine with something in every part of the state.
+    fn busy_machine(variant: Variant) -> Interpreter {
+        let mut interpreter = setup_emulator(variant, Quirks::default());
+        // hires, I := font, V0 := 5, draw, call a subroutine that loops.
+        let program = [
+            0x00, 0xFF, 0xA0, 0x00, 0x60, 0x05, 0xD0, 0x05, 0x22, 0x0A, 0x12, 0x0A,
+        ];
+        load_rom(&mut interpreter, &program);
+        interpreter.rng = Rng::new(RngMode::Xorshift, 3);
+        interpreter.input[7] = true;
+        interpreter.rpl_flags[2] = 0x42;
+        run_frame(&mut interpreter, 8).unwrap();
+        interpreter.cpu.delay_timer = 9;
+        interpreter
+    }
+
+    #[test]
+    fn states_load_back_byte_for_byte() {
+        let original = busy_machine(Variant::XoChip);
+        let state = save_state(&original);
+        let mut loaded = setup_emulator(Variant::XoChip, Quirks::default());
+        load_state(&mut loaded, &state).unwrap();
+        assert_eq!(save_state(&loaded), state);
+        assert_eq!(loaded.cpu.stack, [PROGRAM_START + 10]);
+        assert!(loaded.screen.is_hires());
+    }
+
+    #[test]
+    fn truncated_states_leave_the_machine_alone() {
+        let state = save_state(&busy_machine(Variant::SuperChip));
+        let mut interpreter = setup_emulator(Variant::SuperChip, Quirks::default());
+        let before = save_state(&interpreter);
+        for len in [5, 40, state.len() - 1] {
+            assert_eq!(
+                load_state(&mut interpreter, &state[..len]),
+                Err(StateError::Truncated)
+            );
+        }
+        assert_eq!(save_state(&interpreter), before);
+    }
+
+    #[test]
+    fn states_only_load_into_their_variant() {
+        let state = save_state(&busy_machine(Variant::XoChip));
+        let mut interpreter = setup_emulator(Variant::Chip8, Quirks::default());
+        assert_eq!(
+            load_state(&mut interpreter, &state),
+            Err(StateError::VariantMismatch)
+        );
+    }
+
+    #[test]
+    fn version_1_states_keep_the_current_rng() {
+        let original = busy_machine(Variant::SuperChip);
+        let state = save_state(&original);
+        // Version 1 is version 2 without the generator at the end.
+        let mut old = state[..state.len() - 9].to_vec();
+        old[MAGIC.len()] = 1;
+        let mut interpreter = setup_emulator(Variant::SuperChip, Quirks::default());
+        let rng = Rng::new(RngMode::Table, 11);
+        interpreter.rng = rng;
+        load_state(&mut interpreter, &old).unwrap();
+        assert_eq!(interpreter.rng, rng);
+        interpreter.rng = original.rng;
+        assert_eq!(save_state(&interpreter), state);
+    }
+}
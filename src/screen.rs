@@ -0,0 +1,141 @@
+pub const LORES_WIDTH: usize = 64;
+pub const LORES_HEIGHT: usize = 32;
+pub const HIRES_WIDTH: usize = 128;
+pub const HIRES_HEIGHT: usize = 64;
+
+/// The scale a window or image of 64x32 times `scale` really gets: odd
+/// scales are rounded up so hires pixels are whole pixels as well.
+pub fn even_scale(scale: usize) -> usize {
+    scale.max(1).next_multiple_of(2)
+}
+
+/// The framebuffer, either 64x32 (lores) or the SUPER-CHIP 128x64 (hires).
+/// Pixels are stored row by row, each as a bit mask of the XO-CHIP planes it
+/// is lit on. Drawing, clearing and scrolling only touch the selected planes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Screen {
+    hires: bool,
+    planes: u8,
+    pixels: Vec<u8>,
+}
+
+impl Default for Screen {
+    fn default() -> Screen {
+        Screen::new()
+    }
+}
+
+impl Screen {
+    pub fn new() -> Screen {
+        Screen {
+            hires: false,
+            planes: 1,
+            pixels: vec![0; LORES_WIDTH * LORES_HEIGHT],
+        }
+    }
+
+    /// Rebuilds a screen from what `pixels` returned, e.g. for save states.
+    pub(crate) fn from_parts(hires: bool, planes: u8, pixels: Vec<u8>) -> Option<Screen> {
+        let screen = Screen {
+            hires,
+            planes: planes & 0x3,
+            pixels,
+        };
+        (screen.pixels.len() == screen.width() * screen.height()).then_some(screen)
+    }
+
+    pub(crate) fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    pub fn width(&self) -> usize {
+        if self.hires {
+            HIRES_WIDTH
+        } else {
+            LORES_WIDTH
+        }
+    }
+
+    pub fn height(&self) -> usize {
+        if self.hires {
+            HIRES_HEIGHT
+        } else {
+            LORES_HEIGHT
+        }
+    }
+
+    pub fn is_hires(&self) -> bool {
+        self.hires
+    }
+
+    /// Switches the resolution, which also clears all planes.
+    pub fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.pixels = vec![0; self.width() * self.height()];
+    }
+
+    pub fn selected_planes(&self) -> u8 {
+        self.planes
+    }
+
+    pub fn select_planes(&mut self, planes: u8) {
+        self.planes = planes & 0x3;
+    }
+
+    /// The planes a pixel is lit on, 0 for a pixel that is off.
+    pub fn get(&self, x: usize, y: usize) -> u8 {
+        self.pixels[y * self.width() + x]
+    }
+
+    pub fn is_lit(&self, x: usize, y: usize) -> bool {
+        self.get(x, y) != 0
+    }
+
+    /// XORs a pixel on one plane and returns whether it was already set.
+    pub fn flip(&mut self, x: usize, y: usize, plane: u8) -> bool {
+        let width = self.width();
+        let pixel = &mut self.pixels[y * width + x];
+        let was_active = *pixel & plane != 0;
+        *pixel ^= plane;
+        was_active
+    }
+
+    pub fn clear(&mut self) {
+        let keep = !self.planes;
+        for pixel in self.pixels.iter_mut() {
+            *pixel &= keep;
+        }
+    }
+
+    pub fn scroll_down(&mut self, rows: usize) {
+        let width = self.width();
+        self.scroll(|x, y| y.checked_sub(rows).map(|y| (x, y)), width);
+    }
+
+    pub fn scroll_right(&mut self, columns: usize) {
+        let width = self.width();
+        self.scroll(|x, y| x.checked_sub(columns).map(|x| (x, y)), width);
+    }
+
+    pub fn scroll_left(&mut self, columns: usize) {
+        let width = self.width();
+        self.scroll(
+            |x, y| Some((x + columns, y)).filter(|&(x, _)| x < width),
+            width,
+        );
+    }
+
+    /// Moves the selected planes, `source` maps each pixel to the one it is
+    /// copied from or `None` where empty space scrolls in.
+    fn scroll(&mut self, source: impl Fn(usize, usize) -> Option<(usize, usize)>, width: usize) {
+        let planes = self.planes;
+        let old = self.pixels.clone();
+        for (index, pixel) in self.pixels.iter_mut().enumerate() {
+            let moved = match source(index % width, index / width) {
+                Some((x, y)) => old[y * width + x] & planes,
+                None => 0,
+            };
+            *pixel = (*pixel & !planes) | moved;
+        }
+    }
+}
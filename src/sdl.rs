@@ -0,0 +1,361 @@
+use crate::audio::{AudioPattern, AudioSettings, ToneGenerator};
+use crate::frontend::{text_pixels, Audio, Display, Hotkey, Keymap, Keypad, Palette};
+use crate::screen::{even_scale, LORES_HEIGHT, LORES_WIDTH};
+use crate::Screen;
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use sdl2::controller::{Axis, Button, GameController};
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::Canvas;
+use sdl2::{EventPump, GameControllerSubsystem};
+use std::collections::HashSet;
+
+pub const DEFAULT_SCALE: usize = 10;
+/// How far the left stick has to be pushed to count as a D-pad direction,
+/// out of 32767.
+const STICK_DEADZONE: i16 = 12_000;
+
+pub struct SdlDisplay {
+    canvas: Canvas<sdl2::video::Window>,
+    scale: usize,
+    pub palette: Palette,
+    /// Drawn over the top left corner of the screen.
+    status: String,
+}
+
+pub struct SdlKeypad {
+    event_pump: EventPump,
+    /// `None` if SDL could not set up game controllers.
+    controller_subsystem: Option<GameControllerSubsystem>,
+    /// Kept open to get their events.
+    controllers: Vec<GameController>,
+    bindings: Vec<(HostInput, usize)>,
+    /// Bound inputs that are down, a CHIP-8 key is held while any of its inputs are.
+    held: HashSet<HostInput>,
+}
+
+/// Something on the host that can press a CHIP-8 key.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum HostInput {
+    Key(Keycode),
+    /// A button of any connected game controller.
+    Button(Button),
+    /// The left stick pushed in the direction of a D-pad button.
+    Stick(Button),
+}
+
+/// Looks up the host keys of `keymap`. Keys are matched by what they are
+/// labeled on the user's keyboard layout, not by their position. Game
+/// controller buttons are named `Pad A`, `Pad Start`, `Pad Up` and so on,
+/// the directions also take the left stick.
+pub fn resolve_keymap(keymap: &Keymap) -> Result<Vec<(HostInput, usize)>, String> {
+    let mut bindings = Vec::new();
+    for (host, key) in keymap.bindings() {
+        let key = key as usize;
+        if let Some(name) = host.strip_prefix("Pad ") {
+            let button = match name.to_ascii_lowercase().as_str() {
+                "up" => Button::DPadUp,
+                "down" => Button::DPadDown,
+                "left" => Button::DPadLeft,
+                "right" => Button::DPadRight,
+                name => Button::from_string(name)
+                    .ok_or_else(|| format!("Unknown controller button {} in keymap", host))?,
+            };
+            bindings.push((HostInput::Button(button), key));
+            if matches!(
+                button,
+                Button::DPadUp | Button::DPadDown | Button::DPadLeft | Button::DPadRight
+            ) {
+                bindings.push((HostInput::Stick(button), key));
+            }
+            continue;
+        }
+        let keycode =
+            Keycode::from_name(host).ok_or_else(|| format!("Unknown key {} in keymap", host))?;
+        bindings.push((HostInput::Key(keycode), key));
+    }
+    Ok(bindings)
+}
+
+pub struct SdlAudio {
+    device: Option<AudioDevice<ToneCallback>>,
+}
+
+struct ToneCallback {
+    generator: ToneGenerator,
+    sample_rate: u32,
+}
+
+impl AudioCallback for ToneCallback {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        self.generator.fill(out, self.sample_rate);
+    }
+}
+
+pub fn setup_frontend(
+    audio_settings: AudioSettings,
+    scale: usize,
+    bindings: Vec<(HostInput, usize)>,
+) -> (SdlDisplay, SdlKeypad, SdlAudio) {
+    let scale = even_scale(scale);
+    let sdl_context = sdl2::init().unwrap();
+    let video_subsystem = sdl_context.video().unwrap();
+
+    let window = video_subsystem
+        .window(
+            "chip-8 emulator",
+            (LORES_WIDTH * scale).try_into().unwrap(),
+            (LORES_HEIGHT * scale).try_into().unwrap(),
+        )
+        .position_centered()
+        .build()
+        .expect("window creation failed");
+
+    let canvas = window.into_canvas().build().expect("Canvas Builder failed");
+
+    let event_pump = sdl_context.event_pump().unwrap();
+
+    // Controllers connected already are announced by events like hotplugged ones.
+    let controller_subsystem = sdl_context
+        .game_controller()
+        .inspect_err(|err| eprintln!("Game controllers disabled: {}", err))
+        .ok();
+
+    // A missing audio device should not keep games from running.
+    let device = sdl_context
+        .audio()
+        .and_then(|audio_subsystem| {
+            let desired = AudioSpecDesired {
+                freq: Some(44_100),
+                channels: Some(1),
+                samples: Some(512),
+            };
+            audio_subsystem.open_playback(None, &desired, |spec| ToneCallback {
+                generator: ToneGenerator::new(audio_settings),
+                sample_rate: spec.freq as u32,
+            })
+        })
+        .inspect(|device| device.resume())
+        .inspect_err(|err| eprintln!("Audio disabled: {}", err))
+        .ok();
+
+    (
+        SdlDisplay {
+            canvas,
+            scale,
+            palette: Palette::default(),
+            status: String::new(),
+        },
+        SdlKeypad {
+            event_pump,
+            controller_subsystem,
+            controllers: Vec::new(),
+            bindings,
+            held: HashSet::new(),
+        },
+        SdlAudio { device },
+    )
+}
+
+impl Audio for SdlAudio {
+    fn set_beeping(&mut self, beeping: bool) {
+        if let Some(device) = &mut self.device {
+            device.lock().generator.set_beeping(beeping);
+        }
+    }
+
+    fn set_pattern(&mut self, pattern: Option<AudioPattern>) {
+        if let Some(device) = &mut self.device {
+            device.lock().generator.set_pattern(pattern);
+        }
+    }
+}
+
+impl Display for SdlDisplay {
+    fn draw(&mut self, screen: &Screen) {
+        let [r, g, b] = self.palette.color(0);
+        self.canvas.set_draw_color(Color::RGB(r, g, b));
+        self.canvas.clear();
+
+        // The window keeps its size, hires mode just uses smaller pixels.
+        let pixel_size = LORES_WIDTH * self.scale / screen.width();
+        for y in 0..screen.height() {
+            for x in 0..screen.width() {
+                let planes = screen.get(x, y);
+                if planes != 0 {
+                    let [r, g, b] = self.palette.color(planes);
+                    self.canvas.set_draw_color(Color::RGB(r, g, b));
+                    let _ = self.canvas.fill_rect(Rect::new(
+                        (x * pixel_size) as i32,
+                        (y * pixel_size) as i32,
+                        pixel_size as u32,
+                        pixel_size as u32,
+                    ));
+                }
+            }
+        }
+        if !self.status.is_empty() {
+            self.draw_status();
+        }
+        self.canvas.present();
+    }
+
+    fn show_status(&mut self, status: &str) {
+        let title = format!("chip-8 emulator - {}", status);
+        if self.canvas.window().title() != title {
+            let _ = self.canvas.window_mut().set_title(&title);
+        }
+        self.status = status.to_string();
+    }
+}
+
+impl SdlDisplay {
+    /// The status in the plane 1 color on a box of the background color,
+    /// with a margin of one text pixel.
+    fn draw_status(&mut self) {
+        let size = (self.scale / 4).max(1) as u32;
+        let columns = self.status.chars().count() as u32 * 4 + 1;
+        let [r, g, b] = self.palette.color(0);
+        self.canvas.set_draw_color(Color::RGB(r, g, b));
+        let _ = self
+            .canvas
+            .fill_rect(Rect::new(0, 0, columns * size, 7 * size));
+        let [r, g, b] = self.palette.color(1);
+        self.canvas.set_draw_color(Color::RGB(r, g, b));
+        for (x, y) in text_pixels(&self.status) {
+            let _ = self.canvas.fill_rect(Rect::new(
+                ((x + 1) as u32 * size) as i32,
+                ((y + 1) as u32 * size) as i32,
+                size,
+                size,
+            ));
+        }
+    }
+}
+
+impl SdlKeypad {
+    /// Updates the CHIP-8 keys `host` is bound to, false if it is not bound.
+    fn set_held(&mut self, host: HostInput, down: bool, input: &mut [bool; 16]) -> bool {
+        let keys: Vec<usize> = self
+            .bindings
+            .iter()
+            .filter(|&&(bound, _)| bound == host)
+            .map(|&(_, key)| key)
+            .collect();
+        if down {
+            self.held.insert(host);
+        } else {
+            self.held.remove(&host);
+        }
+        for &key in &keys {
+            input[key] = self
+                .bindings
+                .iter()
+                .any(|(bound, other)| *other == key && self.held.contains(bound));
+        }
+        !keys.is_empty()
+    }
+
+    fn connect_controller(&mut self, joystick_index: u32) {
+        let Some(subsystem) = &self.controller_subsystem else {
+            return;
+        };
+        match subsystem.open(joystick_index) {
+            Ok(controller) => {
+                println!("Connected controller {}", controller.name());
+                self.controllers.push(controller);
+            }
+            Err(err) => eprintln!("Could not open controller: {}", err),
+        }
+    }
+
+    fn disconnect_controller(&mut self, instance_id: u32) {
+        if let Some(index) = self
+            .controllers
+            .iter()
+            .position(|controller| controller.instance_id() == instance_id)
+        {
+            println!("Disconnected controller {}", self.controllers[index].name());
+            self.controllers.remove(index);
+        }
+    }
+
+    /// Treats the left stick as a D-pad, with `value` its position on `axis`.
+    fn move_stick(&mut self, axis: Axis, value: i16, input: &mut [bool; 16]) {
+        let (negative, positive) = match axis {
+            Axis::LeftX => (Button::DPadLeft, Button::DPadRight),
+            Axis::LeftY => (Button::DPadUp, Button::DPadDown),
+            _ => return,
+        };
+        self.set_held(HostInput::Stick(negative), value < -STICK_DEADZONE, input);
+        self.set_held(HostInput::Stick(positive), value > STICK_DEADZONE, input);
+    }
+}
+
+impl Keypad for SdlKeypad {
+    fn poll(&mut self, input: &mut [bool; 16]) -> Vec<Hotkey> {
+        let mut hotkeys = Vec::new();
+        for event in self.event_pump.poll_iter().collect::<Vec<_>>() {
+            match event {
+                Event::Quit { .. } => hotkeys.push(Hotkey::Quit),
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    repeat,
+                    ..
+                } => {
+                    if self.set_held(HostInput::Key(keycode), true, input) || repeat {
+                        continue;
+                    }
+                    match keycode {
+                        Keycode::ESCAPE => hotkeys.push(Hotkey::Quit),
+                        Keycode::M => hotkeys.push(Hotkey::ToggleMute),
+                        Keycode::P | Keycode::PAUSE => hotkeys.push(Hotkey::TogglePause),
+                        Keycode::N => hotkeys.push(Hotkey::FrameAdvance),
+                        Keycode::TAB => hotkeys.push(Hotkey::FastForward(true)),
+                        Keycode::BACKSPACE => hotkeys.push(Hotkey::Rewind(true)),
+                        Keycode::L => hotkeys.push(Hotkey::ToggleSlowMotion),
+                        Keycode::F2 => hotkeys.push(Hotkey::Reset),
+                        Keycode::F3 => hotkeys.push(Hotkey::Reload),
+                        Keycode::F5 => hotkeys.push(Hotkey::SaveState),
+                        Keycode::F6 => hotkeys.push(Hotkey::PreviousSlot),
+                        Keycode::F7 => hotkeys.push(Hotkey::NextSlot),
+                        Keycode::F8 => hotkeys.push(Hotkey::Screenshot),
+                        Keycode::F9 => hotkeys.push(Hotkey::LoadState),
+                        Keycode::F10 => hotkeys.push(Hotkey::ToggleCapture),
+                        Keycode::F12 => hotkeys.push(Hotkey::Debug),
+                        _ => (),
+                    }
+                }
+                Event::KeyUp {
+                    keycode: Some(keycode),
+                    ..
+                } => {
+                    let bound = self.set_held(HostInput::Key(keycode), false, input);
+                    match keycode {
+                        _ if bound => (),
+                        Keycode::TAB => hotkeys.push(Hotkey::FastForward(false)),
+                        Keycode::BACKSPACE => hotkeys.push(Hotkey::Rewind(false)),
+                        _ => (),
+                    }
+                }
+                Event::ControllerButtonDown { button, .. } => {
+                    self.set_held(HostInput::Button(button), true, input);
+                }
+                Event::ControllerButtonUp { button, .. } => {
+                    self.set_held(HostInput::Button(button), false, input);
+                }
+                Event::ControllerAxisMotion { axis, value, .. } => {
+                    self.move_stick(axis, value, input)
+                }
+                Event::ControllerDeviceAdded { which, .. } => self.connect_controller(which),
+                Event::ControllerDeviceRemoved { which, .. } => self.disconnect_controller(which),
+                _ => (),
+            }
+        }
+        hotkeys
+    }
+}